@@ -0,0 +1,176 @@
+use crate::model::dot;
+use crate::prng::Prng;
+use clap::Args;
+
+// k-means is deterministic for reproducible output
+const SEED: u64 = 42;
+const ITERATIONS: usize = 10;
+// points per list used to train k-means
+const SAMPLE_RATIO: usize = 64;
+
+// options for approximate nearest neighbor search
+#[derive(Debug, Args)]
+pub struct AnnOptions {
+    #[arg(long)]
+    ann: bool,
+
+    #[arg(long, requires = "ann", value_parser = clap::value_parser!(u32).range(1..))]
+    ann_lists: Option<u32>,
+
+    #[arg(long, requires = "ann", default_value_t = 8, value_parser = clap::value_parser!(u32).range(1..))]
+    ann_probes: u32,
+
+    #[arg(long, requires = "ann", default_value_t = 100)]
+    ann_sample: u32,
+}
+
+impl AnnOptions {
+    pub fn index(&self, factors: &[f32], cols: usize) -> Option<Index> {
+        if !self.ann {
+            return None;
+        }
+
+        let rows = factors.len() / cols;
+        // square root of the number of rows by default
+        let lists = match self.ann_lists {
+            Some(lists) => lists as usize,
+            None => (rows as f64).sqrt().ceil() as usize,
+        };
+        Some(Index::new(factors, cols, lists, self.ann_probes as usize))
+    }
+
+    pub fn sample(&self) -> usize {
+        self.ann_sample as usize
+    }
+}
+
+// inverted file index over normalized factors
+// with spherical k-means for the lists
+pub struct Index {
+    vectors: Vec<f32>,
+    cols: usize,
+    centroids: Vec<f32>,
+    lists: Vec<Vec<usize>>,
+    probes: usize,
+}
+
+impl Index {
+    pub fn new(factors: &[f32], cols: usize, lists: usize, probes: usize) -> Index {
+        let vectors = normalize(factors, cols);
+        let rows = vectors.len() / cols;
+        let lists = lists.clamp(1, rows.max(1));
+
+        let mut sample: Vec<usize> = (0..rows).collect();
+        let mut prng = Prng::new(Some(SEED));
+        prng.shuffle(&mut sample);
+        sample.truncate(lists * SAMPLE_RATIO);
+
+        // start from random points
+        let mut centroids = Vec::with_capacity(lists * cols);
+        for &i in sample.iter().take(lists) {
+            centroids.extend_from_slice(&vectors[(i * cols)..((i + 1) * cols)]);
+        }
+        let lists = centroids.len() / cols;
+
+        for _ in 0..ITERATIONS {
+            let mut sums = vec![0.0; lists * cols];
+            for &i in &sample {
+                let row = &vectors[(i * cols)..((i + 1) * cols)];
+                let c = nearest(&centroids, cols, row);
+                for (s, v) in sums[(c * cols)..((c + 1) * cols)].iter_mut().zip(row) {
+                    *s += v;
+                }
+            }
+
+            // keep the previous centroid for empty lists
+            for (centroid, sum) in centroids
+                .chunks_exact_mut(cols)
+                .zip(sums.chunks_exact(cols))
+            {
+                let norm = dot(sum, sum).sqrt();
+                if norm > 0.0 {
+                    for (c, s) in centroid.iter_mut().zip(sum) {
+                        *c = s / norm;
+                    }
+                }
+            }
+        }
+
+        let mut index_lists = vec![Vec::new(); lists];
+        for (i, row) in vectors.chunks_exact(cols).enumerate() {
+            index_lists[nearest(&centroids, cols, row)].push(i);
+        }
+
+        Index {
+            vectors,
+            cols,
+            centroids,
+            lists: index_lists,
+            probes,
+        }
+    }
+
+    // cosine similarity and ordering like exact search for the candidates
+    pub fn search<F>(&self, i: usize, count: usize, filter: F) -> Vec<(usize, f32)>
+    where
+        F: Fn(usize) -> bool,
+    {
+        let query = self.row(i);
+
+        let mut lists: Vec<(usize, f32)> = self
+            .centroids
+            .chunks_exact(self.cols)
+            .map(|c| dot(c, query))
+            .enumerate()
+            .collect();
+        lists.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+        let mut candidates: Vec<usize> = lists
+            .iter()
+            .take(self.probes)
+            .flat_map(|(l, _)| &self.lists[*l])
+            .copied()
+            .collect();
+        candidates.sort_unstable();
+
+        let mut predictions: Vec<(usize, f32)> = candidates
+            .into_iter()
+            .map(|j| (j, dot(self.row(j), query)))
+            .collect();
+        predictions.sort_by(|a, b| b.1.total_cmp(&a.1));
+        predictions
+            .into_iter()
+            .filter(|v| v.0 != i && filter(v.0))
+            .take(count)
+            .collect()
+    }
+
+    fn row(&self, i: usize) -> &[f32] {
+        &self.vectors[(i * self.cols)..((i + 1) * self.cols)]
+    }
+}
+
+fn normalize(factors: &[f32], cols: usize) -> Vec<f32> {
+    let mut vectors = factors.to_vec();
+    for row in vectors.chunks_exact_mut(cols) {
+        // avoid dividing by zero
+        let norm = dot(row, row).sqrt().max(f32::EPSILON);
+        for v in row {
+            *v /= norm;
+        }
+    }
+    vectors
+}
+
+fn nearest(centroids: &[f32], cols: usize, row: &[f32]) -> usize {
+    let mut best = 0;
+    let mut best_score = f32::NEG_INFINITY;
+    for (c, centroid) in centroids.chunks_exact(cols).enumerate() {
+        let score = dot(centroid, row);
+        if score > best_score {
+            best = c;
+            best_score = score;
+        }
+    }
+    best
+}
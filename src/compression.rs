@@ -0,0 +1,116 @@
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+#[derive(Clone, Debug)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    pub fn variants() -> [&'static str; 3] {
+        ["none", "gzip", "zstd"]
+    }
+
+    // infer from extension
+    pub fn from_path(path: &Path) -> Compression {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("gz") => Compression::Gzip,
+            Some("zst") => Compression::Zstd,
+            _ => Compression::None,
+        }
+    }
+
+    pub fn reader<R: Read + 'static>(&self, rdr: R) -> io::Result<Box<dyn Read>> {
+        Ok(match self {
+            Compression::None => Box::new(rdr),
+            // handle files with multiple members like pigz output
+            Compression::Gzip => Box::new(MultiGzDecoder::new(rdr)),
+            Compression::Zstd => Box::new(zstd::Decoder::new(rdr)?),
+        })
+    }
+
+    pub fn writer<W: Write + Send + 'static>(&self, wtr: W) -> io::Result<CompressedWriter> {
+        let wtr: Box<dyn Write + Send> = Box::new(wtr);
+        Ok(match self {
+            Compression::None => CompressedWriter::None(wtr),
+            Compression::Gzip => {
+                CompressedWriter::Gzip(GzEncoder::new(wtr, flate2::Compression::default()))
+            }
+            Compression::Zstd => CompressedWriter::Zstd(zstd::Encoder::new(wtr, 0)?),
+        })
+    }
+}
+
+// call finish to write the end of the stream and report errors
+pub enum CompressedWriter {
+    None(Box<dyn Write + Send>),
+    Gzip(GzEncoder<Box<dyn Write + Send>>),
+    Zstd(zstd::Encoder<'static, Box<dyn Write + Send>>),
+}
+
+impl CompressedWriter {
+    pub fn finish(self) -> io::Result<()> {
+        let mut wtr = match self {
+            CompressedWriter::None(wtr) => wtr,
+            CompressedWriter::Gzip(wtr) => wtr.finish()?,
+            CompressedWriter::Zstd(wtr) => wtr.finish()?,
+        };
+        wtr.flush()
+    }
+}
+
+impl Write for CompressedWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            CompressedWriter::None(wtr) => wtr.write(buf),
+            CompressedWriter::Gzip(wtr) => wtr.write(buf),
+            CompressedWriter::Zstd(wtr) => wtr.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            CompressedWriter::None(wtr) => wtr.flush(),
+            CompressedWriter::Gzip(wtr) => wtr.flush(),
+            CompressedWriter::Zstd(wtr) => wtr.flush(),
+        }
+    }
+}
+
+impl FromStr for Compression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Compression, Self::Err> {
+        match s {
+            "none" => Ok(Compression::None),
+            "gzip" => Ok(Compression::Gzip),
+            "zstd" => Ok(Compression::Zstd),
+            // not shown since possible_values used
+            _ => Err(format!("Invalid compression: {s}")),
+        }
+    }
+}
+
+impl std::fmt::Display for Compression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Compression::None => write!(f, "none"),
+            Compression::Gzip => write!(f, "gzip"),
+            Compression::Zstd => write!(f, "zstd"),
+        }
+    }
+}
+
+// extension before any compression extension (csv for data.csv.gz)
+pub fn format_extension(path: &Path) -> Option<&str> {
+    match Compression::from_path(path) {
+        Compression::None => path.extension(),
+        _ => Path::new(path.file_stem()?).extension(),
+    }
+    .and_then(|e| e.to_str())
+}
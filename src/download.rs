@@ -1,4 +1,5 @@
 use crate::helpers::*;
+use crate::output::{Column, RecordWriter};
 use sha2::{Digest, Sha256};
 use std::collections::HashMap;
 use std::convert::TryInto;
@@ -112,6 +113,19 @@ fn download_file(url: &str, expected_hash: &str) -> Result<Vec<u8>, Box<dyn Erro
     Ok(contents)
 }
 
+// same columns for all datasets
+fn create_ratings(output: &Path, overwrite: bool) -> Result<RecordWriter, Box<dyn Error>> {
+    RecordWriter::create(
+        output,
+        &[
+            ("user_id", Column::String),
+            ("item_id", Column::String),
+            ("rating", Column::Float),
+        ],
+        overwrite,
+    )
+}
+
 fn download_movielens_100k(output: &Path, overwrite: bool) -> Result<(), Box<dyn Error>> {
     let mut movies = HashMap::new();
 
@@ -152,9 +166,7 @@ fn download_movielens_100k(output: &Path, overwrite: bool) -> Result<(), Box<dyn
         }
     }
 
-    let file = create_file(output, overwrite)?;
-    let mut wtr = csv::Writer::from_writer(file);
-    wtr.write_record(["user_id", "item_id", "rating"])?;
+    let mut wtr = create_ratings(output, overwrite)?;
 
     let ratings_data = archive.by_name("ml-100k/u.data")?;
     let mut rdr = csv::ReaderBuilder::new()
@@ -169,7 +181,7 @@ fn download_movielens_100k(output: &Path, overwrite: bool) -> Result<(), Box<dyn
         wtr.write_record([user_id, movies.get(&item_id).unwrap().to_string(), rating])?;
     }
 
-    wtr.flush()?;
+    wtr.finish()?;
 
     Ok(())
 }
@@ -202,9 +214,7 @@ fn download_movielens_1m(output: &Path, overwrite: bool) -> Result<(), Box<dyn E
         }
     }
 
-    let file = create_file(output, overwrite)?;
-    let mut wtr = csv::Writer::from_writer(file);
-    wtr.write_record(["user_id", "item_id", "rating"])?;
+    let mut wtr = create_ratings(output, overwrite)?;
 
     let ratings_data = archive.by_name("ml-1m/ratings.dat")?;
     let rdr = BufReader::new(ratings_data);
@@ -217,7 +227,7 @@ fn download_movielens_1m(output: &Path, overwrite: bool) -> Result<(), Box<dyn E
         wtr.write_record([user_id, movies.get(&item_id).unwrap().to_string(), rating])?;
     }
 
-    wtr.flush()?;
+    wtr.finish()?;
 
     Ok(())
 }
@@ -244,9 +254,7 @@ fn download_movielens_25m(output: &Path, overwrite: bool) -> Result<(), Box<dyn
         }
     }
 
-    let file = create_file(output, overwrite)?;
-    let mut wtr = csv::Writer::from_writer(file);
-    wtr.write_record(["user_id", "item_id", "rating"])?;
+    let mut wtr = create_ratings(output, overwrite)?;
 
     // show processing progress since it takes a while
     let bar = progress_bar(25000095, "Processing", "{msg} {wide_bar} {percent}%");
@@ -262,7 +270,7 @@ fn download_movielens_25m(output: &Path, overwrite: bool) -> Result<(), Box<dyn
         bar.inc(1);
     }
 
-    wtr.flush()?;
+    wtr.finish()?;
     bar.finish();
 
     Ok(())
@@ -290,9 +298,7 @@ fn download_movielens_32m(output: &Path, overwrite: bool) -> Result<(), Box<dyn
         }
     }
 
-    let file = create_file(output, overwrite)?;
-    let mut wtr = csv::Writer::from_writer(file);
-    wtr.write_record(["user_id", "item_id", "rating"])?;
+    let mut wtr = create_ratings(output, overwrite)?;
 
     // show processing progress since it takes a while
     let bar = progress_bar(32000204, "Processing", "{msg} {wide_bar} {percent}%");
@@ -308,7 +314,7 @@ fn download_movielens_32m(output: &Path, overwrite: bool) -> Result<(), Box<dyn
         bar.inc(1);
     }
 
-    wtr.flush()?;
+    wtr.finish()?;
     bar.finish();
 
     Ok(())
@@ -336,9 +342,7 @@ fn download_movielens_latest_small(output: &Path, overwrite: bool) -> Result<(),
         }
     }
 
-    let file = create_file(output, overwrite)?;
-    let mut wtr = csv::Writer::from_writer(file);
-    wtr.write_record(["user_id", "item_id", "rating"])?;
+    let mut wtr = create_ratings(output, overwrite)?;
 
     let ratings_data = archive.by_name("ml-latest-small/ratings.csv")?;
     let mut rdr = csv::Reader::from_reader(ratings_data);
@@ -350,7 +354,7 @@ fn download_movielens_latest_small(output: &Path, overwrite: bool) -> Result<(),
         wtr.write_record([user_id, movies.get(&item_id).unwrap().to_string(), rating])?;
     }
 
-    wtr.flush()?;
+    wtr.finish()?;
 
     Ok(())
 }
@@ -377,9 +381,7 @@ fn download_movielens_latest(output: &Path, overwrite: bool) -> Result<(), Box<d
         }
     }
 
-    let file = create_file(output, overwrite)?;
-    let mut wtr = csv::Writer::from_writer(file);
-    wtr.write_record(["user_id", "item_id", "rating"])?;
+    let mut wtr = create_ratings(output, overwrite)?;
 
     // show processing progress since it takes a while
     let bar = progress_bar(27753444, "Processing", "{msg} {wide_bar} {percent}%");
@@ -395,7 +397,7 @@ fn download_movielens_latest(output: &Path, overwrite: bool) -> Result<(), Box<d
         bar.inc(1);
     }
 
-    wtr.flush()?;
+    wtr.finish()?;
     bar.finish();
 
     Ok(())
@@ -414,6 +416,7 @@ pub fn download(
     if !overwrite {
         check_exists(&output)?;
     }
+    RecordWriter::check_format(&output)?;
 
     let usage_url = match dataset {
         Dataset::Movielens100k => {
@@ -443,7 +446,7 @@ pub fn download(
         Dataset::MovielensLatestSmall => download_movielens_latest_small(&output, overwrite),
         Dataset::MovielensLatest => download_movielens_latest(&output, overwrite),
     };
-    if res.is_ok() {
+    if res.is_ok() && !is_stdio(&output) {
         eprintln!("Saved to {}", output.display());
     }
     res
@@ -0,0 +1,312 @@
+use crate::helpers::*;
+use crate::input::*;
+use crate::model::Model;
+use crate::prng::Prng;
+use crate::train::{TrainOptions, filter_support, fit};
+use clap::Args;
+use clap::builder::{PossibleValuesParser, TypedValueParser};
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::path::Path;
+use std::str::FromStr;
+
+#[derive(Clone, Debug)]
+pub enum Split {
+    Random,
+    User,
+    Time,
+}
+
+impl Split {
+    pub fn variants() -> [&'static str; 3] {
+        ["random", "user", "time"]
+    }
+}
+
+impl FromStr for Split {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Split, Self::Err> {
+        match s {
+            "random" => Ok(Split::Random),
+            "user" => Ok(Split::User),
+            "time" => Ok(Split::Time),
+            // not shown since possible_values used
+            _ => Err(format!("Invalid split: {s}")),
+        }
+    }
+}
+
+impl std::fmt::Display for Split {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Split::Random => write!(f, "random"),
+            Split::User => write!(f, "user"),
+            Split::Time => write!(f, "time"),
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct SplitOptions {
+    #[arg(long, default_value_t = Split::Random, value_parser = PossibleValuesParser::new(Split::variants()).map(|s| s.parse::<Split>().unwrap()))]
+    split: Split,
+
+    #[arg(long, default_value_t = 0.2, value_parser = parse_fraction)]
+    test_size: f64,
+
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    leave_out: u32,
+}
+
+impl SplitOptions {
+    pub fn requires_timestamps(&self) -> bool {
+        matches!(self.split, Split::Time)
+    }
+}
+
+pub type Rows = Vec<Row>;
+
+pub fn split(
+    interactions: &Interactions,
+    options: &SplitOptions,
+    seed: Option<u64>,
+) -> (Rows, Rows) {
+    let data = &interactions.data;
+    let timestamps = &interactions.timestamps;
+    let mut prng = Prng::new(seed);
+    let mut test = vec![false; data.len()];
+
+    match options.split {
+        Split::Random => {
+            let mut indices: Vec<usize> = (0..data.len()).collect();
+            prng.shuffle(&mut indices);
+            for i in indices.into_iter().take(test_size(data.len(), options)) {
+                test[i] = true;
+            }
+        }
+        Split::User => {
+            // group by user in order of appearance for reproducibility
+            let mut groups: Vec<Vec<usize>> = Vec::new();
+            let mut group_map: HashMap<u32, usize> = HashMap::new();
+            for (i, &(user, _, _)) in data.iter().enumerate() {
+                let g = *group_map.entry(user).or_insert_with(|| {
+                    groups.push(Vec::new());
+                    groups.len() - 1
+                });
+                groups[g].push(i);
+            }
+
+            let leave_out = options.leave_out as usize;
+            for mut group in groups {
+                // keep at least one interaction for training
+                if group.len() <= leave_out {
+                    continue;
+                }
+
+                // leave out the latest interactions if timestamps are available
+                if timestamps.is_empty() {
+                    prng.shuffle(&mut group);
+                } else {
+                    group.sort_by_key(|&i| std::cmp::Reverse(timestamps[i]));
+                }
+
+                for i in group.into_iter().take(leave_out) {
+                    test[i] = true;
+                }
+            }
+        }
+        Split::Time => {
+            let mut indices: Vec<usize> = (0..data.len()).collect();
+            indices.sort_by_key(|&i| std::cmp::Reverse(timestamps[i]));
+            for i in indices.into_iter().take(test_size(data.len(), options)) {
+                test[i] = true;
+            }
+        }
+    }
+
+    let mut train_set = Vec::with_capacity(data.len());
+    let mut test_set = Vec::new();
+    for (row, is_test) in data.iter().zip(test) {
+        if is_test {
+            test_set.push(*row);
+        } else {
+            train_set.push(*row);
+        }
+    }
+    (train_set, test_set)
+}
+
+fn test_size(len: usize, options: &SplitOptions) -> usize {
+    (len as f64 * options.test_size).round() as usize
+}
+
+pub enum Metrics {
+    Explicit {
+        rmse: f64,
+        mae: f64,
+    },
+    Implicit {
+        precision: f64,
+        recall: f64,
+        map: f64,
+        ndcg: f64,
+    },
+}
+
+impl Metrics {
+    pub fn names(&self, count: usize) -> Vec<String> {
+        match self {
+            Metrics::Explicit { .. } => vec!["RMSE".to_string(), "MAE".to_string()],
+            Metrics::Implicit { .. } => vec![
+                format!("Precision@{count}"),
+                format!("Recall@{count}"),
+                format!("MAP@{count}"),
+                format!("NDCG@{count}"),
+            ],
+        }
+    }
+
+    pub fn values(&self) -> Vec<f64> {
+        match self {
+            Metrics::Explicit { rmse, mae } => vec![*rmse, *mae],
+            Metrics::Implicit {
+                precision,
+                recall,
+                map,
+                ndcg,
+            } => vec![*precision, *recall, *map, *ndcg],
+        }
+    }
+}
+
+pub fn metrics(
+    model: &Model,
+    interactions: &Interactions,
+    test_set: &[Row],
+    count: usize,
+) -> Result<Metrics, Box<dyn Error>> {
+    if model.explicit() {
+        Ok(explicit_metrics(model, interactions, test_set))
+    } else {
+        implicit_metrics(model, interactions, test_set, count)
+    }
+}
+
+fn explicit_metrics(model: &Model, interactions: &Interactions, test_set: &[Row]) -> Metrics {
+    let mut squared_error = 0.0;
+    let mut absolute_error = 0.0;
+    for &(user, item, value) in test_set {
+        let prediction = model.predict(
+            &interactions.user_ids[user as usize],
+            &interactions.item_ids[item as usize],
+        );
+        let error = (value - prediction) as f64;
+        squared_error += error * error;
+        absolute_error += error.abs();
+    }
+
+    let n = test_set.len() as f64;
+    Metrics::Explicit {
+        rmse: (squared_error / n).sqrt(),
+        mae: absolute_error / n,
+    }
+}
+
+fn implicit_metrics(
+    model: &Model,
+    interactions: &Interactions,
+    test_set: &[Row],
+    count: usize,
+) -> Result<Metrics, Box<dyn Error>> {
+    let mut relevant: HashMap<&str, HashSet<&str>> = HashMap::new();
+    for &(user, item, _) in test_set {
+        relevant
+            .entry(&interactions.user_ids[user as usize])
+            .or_default()
+            .insert(&interactions.item_ids[item as usize]);
+    }
+
+    let mut precision = 0.0;
+    let mut recall = 0.0;
+    let mut map = 0.0;
+    let mut ndcg = 0.0;
+    let mut users = 0;
+    for (user_id, items) in &relevant {
+        // users not in the training set have no recs
+        if !model.has_user(user_id) {
+            continue;
+        }
+
+        let recs = model.user_recs(user_id, count);
+
+        let mut hits = 0;
+        let mut average_precision = 0.0;
+        let mut dcg = 0.0;
+        for (i, (item_id, _)) in recs.iter().enumerate() {
+            if items.contains(item_id.as_str()) {
+                hits += 1;
+                average_precision += hits as f64 / (i + 1) as f64;
+                dcg += 1.0 / (i as f64 + 2.0).log2();
+            }
+        }
+
+        let ideal = items.len().min(count);
+        let idcg: f64 = (0..ideal).map(|i| 1.0 / (i as f64 + 2.0).log2()).sum();
+
+        precision += hits as f64 / count as f64;
+        recall += hits as f64 / items.len() as f64;
+        map += average_precision / ideal as f64;
+        ndcg += dcg / idcg;
+        users += 1;
+    }
+
+    if users == 0 {
+        return Err("No test users in the training set".into());
+    }
+
+    let n = users as f64;
+    Ok(Metrics::Implicit {
+        precision: precision / n,
+        recall: recall / n,
+        map: map / n,
+        ndcg: ndcg / n,
+    })
+}
+
+pub fn evaluate(
+    input: &Path,
+    input_options: &InputOptions,
+    split_options: &SplitOptions,
+    train_options: &TrainOptions,
+    count: usize,
+    overwrite: bool,
+) -> Result<(), Box<dyn Error>> {
+    if !overwrite {
+        train_options.check_exists()?;
+    }
+
+    let mut interactions =
+        read_interactions(input, input_options, split_options.requires_timestamps())?;
+    filter_support(&mut interactions, train_options)?;
+
+    let (train_set, test_set) = split(&interactions, split_options, train_options.seed);
+    if train_set.is_empty() || test_set.is_empty() {
+        return Err("Not enough data to split".into());
+    }
+    eprintln!(
+        "Split {} interactions into {} training and {} test",
+        interactions.data.len(),
+        train_set.len(),
+        test_set.len()
+    );
+
+    let model = fit(&interactions, &train_set, train_options, overwrite)?;
+
+    let metrics = metrics(&model, &interactions, &test_set, count)?;
+    for (name, value) in metrics.names(count).iter().zip(metrics.values()) {
+        println!("{name}: {value:.4}");
+    }
+
+    Ok(())
+}
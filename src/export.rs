@@ -0,0 +1,192 @@
+use crate::compression::format_extension;
+use crate::helpers::*;
+use crate::train::{ModelOptions, load_model};
+use std::error::Error;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+#[derive(Clone, Debug)]
+pub enum ExportFormat {
+    Csv,
+    Jsonl,
+    Npy,
+}
+
+impl ExportFormat {
+    pub fn variants() -> [&'static str; 3] {
+        ["csv", "jsonl", "npy"]
+    }
+
+    // infer from extension
+    fn from_path(path: &Path) -> ExportFormat {
+        match format_extension(path) {
+            Some("jsonl") => ExportFormat::Jsonl,
+            Some("npy") => ExportFormat::Npy,
+            _ => ExportFormat::Csv,
+        }
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ExportFormat, Self::Err> {
+        match s {
+            "csv" => Ok(ExportFormat::Csv),
+            "jsonl" => Ok(ExportFormat::Jsonl),
+            "npy" => Ok(ExportFormat::Npy),
+            // not shown since possible_values used
+            _ => Err(format!("Invalid format: {s}")),
+        }
+    }
+}
+
+impl std::fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExportFormat::Csv => write!(f, "csv"),
+            ExportFormat::Jsonl => write!(f, "jsonl"),
+            ExportFormat::Npy => write!(f, "npy"),
+        }
+    }
+}
+
+// npy stores the matrix only, so ids are saved next to it
+fn ids_path(path: &Path) -> PathBuf {
+    path.with_extension("ids.csv")
+}
+
+fn write_factors(
+    path: &Path,
+    format: &ExportFormat,
+    id_header: &str,
+    ids: &[String],
+    factors: &[f32],
+    cols: usize,
+    overwrite: bool,
+) -> Result<(), Box<dyn Error>> {
+    match format {
+        ExportFormat::Csv => {
+            let mut wtr = create_csv(path, overwrite)?;
+            let mut header = vec![id_header.to_string()];
+            header.extend((0..cols).map(|i| format!("factor_{i}")));
+            wtr.write_record(&header)?;
+            for (id, row) in ids.iter().zip(factors.chunks_exact(cols)) {
+                let mut record = vec![id.to_string()];
+                record.extend(row.iter().map(|v| v.to_string()));
+                wtr.write_record(&record)?;
+            }
+            finish_csv(wtr)?;
+        }
+        ExportFormat::Jsonl => {
+            let mut wtr = BufWriter::new(create_output(path, overwrite)?);
+            for (id, row) in ids.iter().zip(factors.chunks_exact(cols)) {
+                let values: Vec<String> = row.iter().map(|v| v.to_string()).collect();
+                writeln!(
+                    wtr,
+                    "{{\"{}\": {}, \"factors\": [{}]}}",
+                    id_header,
+                    json_string(id),
+                    values.join(", ")
+                )?;
+            }
+            finish_output(wtr)?;
+        }
+        ExportFormat::Npy => {
+            let mut wtr = BufWriter::new(create_output(path, overwrite)?);
+
+            // https://numpy.org/doc/stable/reference/generated/numpy.lib.format.html
+            let mut header = format!(
+                "{{'descr': '<f4', 'fortran_order': False, 'shape': ({}, {}), }}",
+                ids.len(),
+                cols
+            );
+            // pad so data is aligned to 64 bytes
+            let len = 10 + header.len() + 1;
+            header.push_str(&" ".repeat(len.next_multiple_of(64) - len));
+            header.push('\n');
+
+            wtr.write_all(b"\x93NUMPY\x01\x00")?;
+            wtr.write_all(&(header.len() as u16).to_le_bytes())?;
+            wtr.write_all(header.as_bytes())?;
+            for v in factors {
+                wtr.write_all(&v.to_le_bytes())?;
+            }
+            finish_output(wtr)?;
+
+            let mut wtr = create_csv(&ids_path(path), overwrite)?;
+            wtr.write_record([id_header])?;
+            for id in ids {
+                wtr.write_record([id])?;
+            }
+            finish_csv(wtr)?;
+        }
+    }
+
+    Ok(())
+}
+
+pub fn export_factors(
+    model_options: &ModelOptions,
+    users: Option<&Path>,
+    items: Option<&Path>,
+    format: Option<&ExportFormat>,
+    overwrite: bool,
+) -> Result<(), Box<dyn Error>> {
+    let format_for = |path: &Path| {
+        format
+            .cloned()
+            .unwrap_or_else(|| ExportFormat::from_path(path))
+    };
+    let users = users.map(|path| (path, format_for(path)));
+    let items = items.map(|path| (path, format_for(path)));
+    // ids are saved to a separate file
+    for (path, format) in users.iter().chain(items.iter()) {
+        if matches!(format, ExportFormat::Npy) && is_stdio(path) {
+            return Err("npy output to stdout not supported".into());
+        }
+    }
+    if !overwrite {
+        for (path, format) in users.iter().chain(items.iter()) {
+            check_exists(path)?;
+            if matches!(format, ExportFormat::Npy) {
+                check_exists(&ids_path(path))?;
+            }
+        }
+    }
+
+    let model = load_model(model_options, overwrite)?;
+
+    if let Some((path, format)) = &users {
+        write_factors(
+            path,
+            format,
+            "user_id",
+            model.user_ids(),
+            model.user_factors(),
+            model.factors(),
+            overwrite,
+        )?;
+        if !is_stdio(path) {
+            eprintln!("Saved to {}", path.display());
+        }
+    }
+
+    if let Some((path, format)) = &items {
+        write_factors(
+            path,
+            format,
+            "item_id",
+            model.item_ids(),
+            model.item_factors(),
+            model.factors(),
+            overwrite,
+        )?;
+        if !is_stdio(path) {
+            eprintln!("Saved to {}", path.display());
+        }
+    }
+
+    Ok(())
+}
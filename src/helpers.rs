@@ -1,7 +1,9 @@
+use crate::compression::{CompressedWriter, Compression};
 use indicatif::{ProgressBar, ProgressStyle};
 use std::error::Error;
+use std::fmt::Display;
 use std::fs::{File, OpenOptions};
-use std::io::ErrorKind;
+use std::io::{self, BufWriter, ErrorKind, Read};
 use std::path::Path;
 
 fn file_exists_message(path: &Path) -> String {
@@ -13,15 +15,90 @@ fn file_exists_message(path: &Path) -> String {
 
 // check upfront (in addition to when opening) for better user experience (fail fast)
 pub fn check_exists(path: &Path) -> Result<(), Box<dyn Error>> {
-    if path.exists() {
+    if !is_stdio(path) && path.exists() {
         return Err(file_exists_message(path).into());
     }
     Ok(())
 }
 
-pub fn create_csv(path: &Path, overwrite: bool) -> Result<csv::Writer<File>, Box<dyn Error>> {
+pub fn open_file(path: &Path) -> Result<File, Box<dyn Error>> {
+    File::open(path).map_err(|e| {
+        if e.kind() == ErrorKind::NotFound {
+            format!("File not found: {}", path.display()).into()
+        } else {
+            e.into()
+        }
+    })
+}
+
+// - for stdin or stdout
+pub fn is_stdio(path: &Path) -> bool {
+    path == Path::new("-")
+}
+
+pub fn open_input(path: &Path) -> Result<Box<dyn Read>, Box<dyn Error>> {
+    open_compressed(path, &Compression::from_path(path))
+}
+
+pub fn open_compressed(
+    path: &Path,
+    compression: &Compression,
+) -> Result<Box<dyn Read>, Box<dyn Error>> {
+    if is_stdio(path) {
+        return Ok(compression.reader(io::stdin())?);
+    }
+    Ok(compression.reader(open_file(path)?)?)
+}
+
+// read the given columns from a CSV file with a header
+pub fn read_columns<const N: usize>(
+    path: &Path,
+    columns: [&str; N],
+) -> Result<Vec<[String; N]>, Box<dyn Error>> {
+    let mut rdr = csv::Reader::from_reader(open_input(path)?);
+
+    let headers = rdr.headers()?;
+    let mut indexes = [0; N];
+    for (index, column) in indexes.iter_mut().zip(columns) {
+        *index = headers
+            .iter()
+            .position(|r| r == column)
+            .ok_or_else(|| format!("Missing {column} column: {}", path.display()))?;
+    }
+
+    let mut rows = Vec::new();
+    for result in rdr.records() {
+        let record = result?;
+        // safe to unwrap since csv::Reader checks for same number of columns as header
+        rows.push(indexes.map(|i| record.get(i).unwrap().to_string()));
+    }
+    Ok(rows)
+}
+
+pub fn create_csv(
+    path: &Path,
+    overwrite: bool,
+) -> Result<csv::Writer<CompressedWriter>, Box<dyn Error>> {
+    Ok(csv::Writer::from_writer(create_output(path, overwrite)?))
+}
+
+pub fn finish_csv(wtr: csv::Writer<CompressedWriter>) -> Result<(), Box<dyn Error>> {
+    wtr.into_inner().map_err(|e| e.into_error())?.finish()?;
+    Ok(())
+}
+
+pub fn create_output(path: &Path, overwrite: bool) -> Result<CompressedWriter, Box<dyn Error>> {
+    if is_stdio(path) {
+        return Ok(CompressedWriter::None(Box::new(io::stdout())));
+    }
+    // compress by extension
     let file = create_file(path, overwrite)?;
-    Ok(csv::Writer::from_writer(file))
+    Ok(Compression::from_path(path).writer(file)?)
+}
+
+pub fn finish_output(wtr: BufWriter<CompressedWriter>) -> Result<(), Box<dyn Error>> {
+    wtr.into_inner().map_err(|e| e.into_error())?.finish()?;
+    Ok(())
 }
 
 pub fn create_file(path: &Path, overwrite: bool) -> Result<File, Box<dyn Error>> {
@@ -48,3 +125,159 @@ pub fn progress_bar(len: u64, message: &'static str, template: &str) -> Progress
     bar.set_message(message);
     bar
 }
+
+pub fn parse_fraction(s: &str) -> Result<f64, String> {
+    let value = s.parse::<f64>().map_err(|e| e.to_string())?;
+    if value > 0.0 && value < 1.0 {
+        Ok(value)
+    } else {
+        Err("must be between 0 and 1".to_string())
+    }
+}
+
+// like 30d, with s, m, h, d, or w units
+pub fn parse_duration(s: &str) -> Result<f64, String> {
+    let (number, unit) = s.split_at(s.find(|c: char| c.is_ascii_alphabetic()).unwrap_or(s.len()));
+    let seconds = match unit {
+        "s" => 1.0,
+        "m" => 60.0,
+        "h" => 3600.0,
+        "d" => 86400.0,
+        "w" => 604800.0,
+        _ => return Err("must have a unit (s, m, h, d, or w)".to_string()),
+    };
+    let value = number.parse::<f64>().map_err(|e| e.to_string())?;
+    if value > 0.0 && value.is_finite() {
+        Ok(value * seconds)
+    } else {
+        Err("must be positive".to_string())
+    }
+}
+
+// Unix timestamp or YYYY-MM-DD in UTC
+pub fn parse_time(s: &str) -> Result<i64, String> {
+    if let Ok(timestamp) = s.parse::<i64>() {
+        return Ok(timestamp);
+    }
+
+    let invalid = || "must be a Unix timestamp or YYYY-MM-DD".to_string();
+    let parts: Vec<&str> = s.split('-').collect();
+    let [year, month, day] = parts[..] else {
+        return Err(invalid());
+    };
+    let year = year.parse::<i64>().map_err(|_| invalid())?;
+    let month = month.parse::<i64>().map_err(|_| invalid())?;
+    let day = day.parse::<i64>().map_err(|_| invalid())?;
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let days_in_month = match month {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    };
+    if !(1..=12).contains(&month) || !(1..=days_in_month).contains(&day) {
+        return Err(invalid());
+    }
+
+    // https://howardhinnant.github.io/date_algorithms.html#days_from_civil
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    Ok((era * 146097 + doe - 719468) * 86400)
+}
+
+pub fn json_string(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len() + 2);
+    escaped.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+// JSON has no NaN or infinity
+pub fn json_number<T: Into<f64> + Display + Copy>(value: T) -> String {
+    if value.into().is_finite() {
+        value.to_string()
+    } else {
+        "null".to_string()
+    }
+}
+
+// high water mark of resident memory in bytes (Linux only)
+pub fn peak_memory() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|l| l.starts_with("VmHWM:"))?;
+    let kb = line.split_whitespace().nth(1)?.parse::<u64>().ok()?;
+    Some(kb * 1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_time() {
+        assert_eq!(Ok(1700000000), parse_time("1700000000"));
+        assert_eq!(Ok(-86400), parse_time("-86400"));
+        assert_eq!(Ok(0), parse_time("1970-01-01"));
+        assert_eq!(Ok(1709164800), parse_time("2024-02-29"));
+        assert_eq!(Ok(951782400), parse_time("2000-02-29"));
+        assert_eq!(Ok(1704067200), parse_time("2024-01-01"));
+    }
+
+    #[test]
+    fn test_parse_time_invalid() {
+        for s in [
+            "2023-02-29",
+            "1900-02-29",
+            "2024-04-31",
+            "2024-13-01",
+            "2024-00-10",
+            "2024-01-00",
+            "2024-01",
+            "yesterday",
+            "",
+        ] {
+            assert_eq!(
+                Err("must be a Unix timestamp or YYYY-MM-DD".to_string()),
+                parse_time(s),
+                "{s}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(Ok(30.0), parse_duration("30s"));
+        assert_eq!(Ok(5400.0), parse_duration("1.5h"));
+        assert_eq!(Ok(2592000.0), parse_duration("30d"));
+        assert_eq!(Ok(1209600.0), parse_duration("2w"));
+    }
+
+    #[test]
+    fn test_parse_duration_invalid() {
+        assert_eq!(
+            Err("must have a unit (s, m, h, d, or w)".to_string()),
+            parse_duration("30")
+        );
+        assert_eq!(
+            Err("must have a unit (s, m, h, d, or w)".to_string()),
+            parse_duration("1y")
+        );
+        assert_eq!(Err("must be positive".to_string()), parse_duration("0d"));
+        assert_eq!(Err("must be positive".to_string()), parse_duration("-1d"));
+        assert!(parse_duration("d").is_err());
+    }
+}
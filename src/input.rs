@@ -0,0 +1,1314 @@
+use crate::compression::Compression;
+use crate::helpers::{open_compressed, parse_duration, parse_time, peak_memory};
+use crate::output::*;
+use clap::Args;
+use clap::builder::{PossibleValuesParser, TypedValueParser};
+use csv::{ByteRecord, StringRecord};
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::Path;
+use std::str::{self, FromStr};
+use std::time::Instant;
+
+#[derive(Clone, Debug)]
+pub enum Feedback {
+    Auto,
+    Explicit,
+    Implicit,
+}
+
+impl Feedback {
+    pub fn variants() -> [&'static str; 3] {
+        ["auto", "explicit", "implicit"]
+    }
+}
+
+impl FromStr for Feedback {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Feedback, Self::Err> {
+        match s {
+            "auto" => Ok(Feedback::Auto),
+            "explicit" => Ok(Feedback::Explicit),
+            "implicit" => Ok(Feedback::Implicit),
+            // not shown since possible_values used
+            _ => Err(format!("Invalid feedback: {s}")),
+        }
+    }
+}
+
+impl std::fmt::Display for Feedback {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Feedback::Auto => write!(f, "auto"),
+            Feedback::Explicit => write!(f, "explicit"),
+            Feedback::Implicit => write!(f, "implicit"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum InputFormat {
+    Csv,
+    Parquet,
+}
+
+impl InputFormat {
+    pub fn variants() -> [&'static str; 2] {
+        ["csv", "parquet"]
+    }
+}
+
+impl FromStr for InputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<InputFormat, Self::Err> {
+        match s {
+            "csv" => Ok(InputFormat::Csv),
+            "parquet" => Ok(InputFormat::Parquet),
+            // not shown since possible_values used
+            _ => Err(format!("Invalid format: {s}")),
+        }
+    }
+}
+
+impl std::fmt::Display for InputFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InputFormat::Csv => write!(f, "csv"),
+            InputFormat::Parquet => write!(f, "parquet"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum Duplicates {
+    Error,
+    Sum,
+    Mean,
+    Max,
+    Last,
+    Count,
+}
+
+impl Duplicates {
+    pub fn variants() -> [&'static str; 6] {
+        ["error", "sum", "mean", "max", "last", "count"]
+    }
+}
+
+impl FromStr for Duplicates {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Duplicates, Self::Err> {
+        match s {
+            "error" => Ok(Duplicates::Error),
+            "sum" => Ok(Duplicates::Sum),
+            "mean" => Ok(Duplicates::Mean),
+            "max" => Ok(Duplicates::Max),
+            "last" => Ok(Duplicates::Last),
+            "count" => Ok(Duplicates::Count),
+            // not shown since possible_values used
+            _ => Err(format!("Invalid duplicates: {s}")),
+        }
+    }
+}
+
+impl std::fmt::Display for Duplicates {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Duplicates::Error => write!(f, "error"),
+            Duplicates::Sum => write!(f, "sum"),
+            Duplicates::Mean => write!(f, "mean"),
+            Duplicates::Max => write!(f, "max"),
+            Duplicates::Last => write!(f, "last"),
+            Duplicates::Count => write!(f, "count"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum ValueTransform {
+    Log1p,
+    Binary,
+    Clip(f32),
+    Bm25,
+}
+
+impl FromStr for ValueTransform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ValueTransform, Self::Err> {
+        match s {
+            "log1p" => Ok(ValueTransform::Log1p),
+            "binary" => Ok(ValueTransform::Binary),
+            "bm25" => Ok(ValueTransform::Bm25),
+            _ => match s.strip_prefix("clip:").map(|v| v.parse::<f32>()) {
+                Some(Ok(max)) if max.is_finite() => Ok(ValueTransform::Clip(max)),
+                _ => Err("must be log1p, binary, clip:MAX, or bm25".to_string()),
+            },
+        }
+    }
+}
+
+impl std::fmt::Display for ValueTransform {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ValueTransform::Log1p => write!(f, "log1p"),
+            ValueTransform::Binary => write!(f, "binary"),
+            ValueTransform::Clip(max) => write!(f, "clip:{max}"),
+            ValueTransform::Bm25 => write!(f, "bm25"),
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct InputOptions {
+    #[arg(long)]
+    user_column: Option<String>,
+
+    #[arg(long)]
+    item_column: Option<String>,
+
+    #[arg(long)]
+    value_column: Option<String>,
+
+    #[arg(long)]
+    no_header: bool,
+
+    #[arg(long, default_value_t = Feedback::Auto, value_parser = PossibleValuesParser::new(Feedback::variants()).map(|s| s.parse::<Feedback>().unwrap()))]
+    feedback: Feedback,
+
+    #[arg(long, value_parser = PossibleValuesParser::new(InputFormat::variants()).map(|s| s.parse::<InputFormat>().unwrap()))]
+    input_format: Option<InputFormat>,
+
+    #[arg(long, value_parser = PossibleValuesParser::new(Compression::variants()).map(|s| s.parse::<Compression>().unwrap()))]
+    compression: Option<Compression>,
+
+    #[arg(long, value_parser = PossibleValuesParser::new(Duplicates::variants()).map(|s| s.parse::<Duplicates>().unwrap()))]
+    duplicates: Option<Duplicates>,
+
+    #[arg(long)]
+    timestamp_column: Option<String>,
+
+    #[arg(long, value_parser = parse_duration)]
+    half_life: Option<f64>,
+
+    #[arg(long, value_parser = parse_time)]
+    since: Option<i64>,
+
+    #[arg(long, value_parser = parse_time)]
+    until: Option<i64>,
+
+    #[arg(long, value_parser = ValueTransform::from_str)]
+    value_transform: Option<ValueTransform>,
+
+    #[arg(long)]
+    min_value: Option<f32>,
+
+    #[arg(long)]
+    strict: bool,
+}
+
+impl InputOptions {
+    pub fn strict(&self) -> bool {
+        self.strict
+    }
+
+    // the default column is used when timestamps are needed
+    fn timestamp_column(&self, required: bool) -> Option<&str> {
+        let needed =
+            required || self.half_life.is_some() || self.since.is_some() || self.until.is_some();
+        match &self.timestamp_column {
+            Some(column) => Some(column),
+            None if needed => Some("timestamp"),
+            None => None,
+        }
+    }
+}
+
+// user index, item index, and value
+pub type Row = (u32, u32, f32);
+
+pub struct Interactions {
+    pub data: Vec<Row>,
+    // ids for each index
+    pub user_ids: Vec<String>,
+    pub item_ids: Vec<String>,
+    pub explicit: bool,
+    // only read when a timestamp column is given or used by last
+    pub timestamps: Vec<i64>,
+}
+
+// likely mistakes that are accepted unless --strict
+#[derive(Default)]
+pub struct Problems {
+    pub duplicate_pairs: usize,
+    pub empty_ids: usize,
+    pub non_finite_values: usize,
+}
+
+impl Problems {
+    pub fn messages(&self) -> Vec<String> {
+        let counts = [
+            (self.duplicate_pairs, "duplicate pairs"),
+            (self.empty_ids, "empty ids"),
+            (self.non_finite_values, "non-finite values"),
+        ];
+        counts
+            .iter()
+            .filter(|(count, _)| *count > 0)
+            .map(|(count, name)| format!("{name}: {count}"))
+            .collect()
+    }
+}
+
+fn pair_key(u: u32, i: u32) -> u64 {
+    ((u as u64) << 32) | i as u64
+}
+
+// new indices for ids that are still used
+fn compact(ids: &mut Vec<String>, used: impl Iterator<Item = u32>) -> Vec<u32> {
+    let mut keep = vec![false; ids.len()];
+    for index in used {
+        keep[index as usize] = true;
+    }
+
+    let mut indices = vec![u32::MAX; ids.len()];
+    let mut next = 0;
+    for (index, &k) in indices.iter_mut().zip(&keep) {
+        if k {
+            *index = next;
+            next += 1;
+        }
+    }
+
+    let mut keep = keep.into_iter();
+    ids.retain(|_| keep.next().unwrap());
+    indices
+}
+
+impl Interactions {
+    // keep rows by index and remove ids without rows
+    fn retain<F: Fn(usize) -> bool>(&mut self, keep: F) {
+        let keep: Vec<bool> = (0..self.data.len()).map(keep).collect();
+        let mut k = keep.iter();
+        self.data.retain(|_| *k.next().unwrap());
+        if !self.timestamps.is_empty() {
+            let mut k = keep.iter();
+            self.timestamps.retain(|_| *k.next().unwrap());
+        }
+
+        let users = compact(&mut self.user_ids, self.data.iter().map(|v| v.0));
+        let items = compact(&mut self.item_ids, self.data.iter().map(|v| v.1));
+        for row in &mut self.data {
+            row.0 = users[row.0 as usize];
+            row.1 = items[row.1 as usize];
+        }
+    }
+
+    // since is inclusive and until is exclusive
+    // returns the original index of each kept row
+    fn filter_time(&mut self, since: Option<i64>, until: Option<i64>) -> Vec<usize> {
+        let rows = self.data.len();
+        let keep: Vec<bool> = self
+            .timestamps
+            .iter()
+            .map(|&t| since.is_none_or(|s| t >= s) && until.is_none_or(|u| t < u))
+            .collect();
+        self.retain(|j| keep[j]);
+        eprintln!("Kept {} of {} rows in time range", self.data.len(), rows);
+        (0..rows).filter(|&j| keep[j]).collect()
+    }
+
+    // repeat until all users and items have enough interactions for a k-core
+    pub fn filter_support(
+        &mut self,
+        min_user: usize,
+        min_item: usize,
+        k_core: bool,
+    ) -> Result<(), Box<dyn Error>> {
+        let rows = self.data.len();
+        let users = self.user_ids.len();
+        let items = self.item_ids.len();
+        loop {
+            let mut user_counts = vec![0; self.user_ids.len()];
+            let mut item_counts = vec![0; self.item_ids.len()];
+            for &(u, i, _) in &self.data {
+                user_counts[u as usize] += 1;
+                item_counts[i as usize] += 1;
+            }
+
+            let keep: Vec<bool> = self
+                .data
+                .iter()
+                .map(|&(u, i, _)| {
+                    user_counts[u as usize] >= min_user && item_counts[i as usize] >= min_item
+                })
+                .collect();
+            let before = self.data.len();
+            self.retain(|j| keep[j]);
+            if !k_core || self.data.len() == before {
+                break;
+            }
+        }
+        eprintln!(
+            "Dropped {} users, {} items, and {} rows with too few interactions",
+            users - self.user_ids.len(),
+            items - self.item_ids.len(),
+            rows - self.data.len()
+        );
+        if self.data.is_empty() {
+            return Err("No rows left with enough interactions".into());
+        }
+        Ok(())
+    }
+
+    fn filter_value(&mut self, min_value: f32) -> Result<(), Box<dyn Error>> {
+        let rows = self.data.len();
+        let keep: Vec<bool> = self.data.iter().map(|v| v.2 >= min_value).collect();
+        self.retain(|j| keep[j]);
+        eprintln!(
+            "Kept {} of {} rows with a value of at least {}",
+            self.data.len(),
+            rows,
+            min_value
+        );
+        if self.data.is_empty() {
+            return Err(format!("No rows left with a value of at least {min_value}").into());
+        }
+        Ok(())
+    }
+
+    fn transform(&mut self, transform: &ValueTransform) -> Result<(), Box<dyn Error>> {
+        if self.explicit {
+            return Err("--value-transform requires implicit feedback".into());
+        }
+
+        match transform {
+            ValueTransform::Log1p => {
+                for row in &mut self.data {
+                    row.2 = row.2.ln_1p();
+                }
+            }
+            ValueTransform::Binary => {
+                for row in &mut self.data {
+                    row.2 = if row.2 > 0.0 { 1.0 } else { 0.0 };
+                }
+            }
+            ValueTransform::Clip(max) => {
+                for row in &mut self.data {
+                    row.2 = row.2.min(*max);
+                }
+            }
+            ValueTransform::Bm25 => self.bm25(),
+        }
+        Ok(())
+    }
+
+    // same weighting and defaults as the implicit library, which weights the
+    // item-user matrix (idf is per user and length normalization is per item)
+    fn bm25(&mut self) {
+        const K1: f64 = 100.0;
+        const B: f64 = 0.8;
+
+        // negative values have no weight
+        for row in &mut self.data {
+            row.2 = row.2.max(0.0);
+        }
+
+        let mut user_items = vec![0usize; self.user_ids.len()];
+        let mut item_sums = vec![0.0f64; self.item_ids.len()];
+        for &(u, i, v) in &self.data {
+            user_items[u as usize] += 1;
+            item_sums[i as usize] += v as f64;
+        }
+
+        let n = self.item_ids.len() as f64;
+        // clamp so users with nearly every item don't get negative weights
+        let idf: Vec<f64> = user_items
+            .iter()
+            .map(|&c| (n.ln() - (c as f64).ln_1p()).max(0.0))
+            .collect();
+        let average_sum = item_sums.iter().sum::<f64>() / n.max(1.0);
+        for row in &mut self.data {
+            // all values are zero if the average is
+            let length = if average_sum > 0.0 {
+                item_sums[row.1 as usize] / average_sum
+            } else {
+                1.0
+            };
+            let length_norm = (1.0 - B) + B * length;
+            let v = row.2 as f64;
+            row.2 = (v * (K1 + 1.0) / (K1 * length_norm + v) * idf[row.0 as usize]) as f32;
+        }
+    }
+
+    // halve values every half-life before the latest interaction
+    fn decay(&mut self, half_life: f64) -> Result<(), Box<dyn Error>> {
+        if self.explicit {
+            return Err("--half-life requires implicit feedback".into());
+        }
+
+        let Some(&latest) = self.timestamps.iter().max() else {
+            return Ok(());
+        };
+        for (row, &timestamp) in self.data.iter_mut().zip(&self.timestamps) {
+            let age = (latest - timestamp) as f64;
+            row.2 *= 0.5f64.powf(age / half_life) as f32;
+        }
+        Ok(())
+    }
+
+    // combine rows for the same user and item in order of first appearance
+    // records are the original indices of rows if any were removed
+    fn aggregate(
+        &mut self,
+        duplicates: &Duplicates,
+        records: Option<&[usize]>,
+    ) -> Result<(), Box<dyn Error>> {
+        let has_timestamps = !self.timestamps.is_empty();
+        let mut index: HashMap<u64, usize> = HashMap::new();
+        let mut data: Vec<Row> = Vec::new();
+        let mut counts: Vec<u32> = Vec::new();
+        let mut timestamps = Vec::new();
+        for (j, &(u, i, v)) in self.data.iter().enumerate() {
+            let key = pair_key(u, i);
+            let Some(&k) = index.get(&key) else {
+                index.insert(key, data.len());
+                data.push((u, i, v));
+                counts.push(1);
+                if has_timestamps {
+                    timestamps.push(self.timestamps[j]);
+                }
+                continue;
+            };
+
+            let value = &mut data[k].2;
+            match duplicates {
+                Duplicates::Error => {
+                    return Err(format!(
+                        "Duplicate pair: record {} (user_id: {}, item_id: {}). Use --duplicates to combine.",
+                        records.map_or(j, |r| r[j]) + 1,
+                        self.user_ids[u as usize],
+                        self.item_ids[i as usize]
+                    )
+                    .into());
+                }
+                // rows are 1 when counting (or their weight with --half-life)
+                Duplicates::Sum | Duplicates::Mean | Duplicates::Count => *value += v,
+                Duplicates::Max => *value = value.max(v),
+                // by timestamp if present, otherwise by order
+                Duplicates::Last => {
+                    if !has_timestamps || self.timestamps[j] >= timestamps[k] {
+                        *value = v;
+                    }
+                }
+            }
+            counts[k] += 1;
+            // keep the latest timestamp
+            if has_timestamps {
+                timestamps[k] = timestamps[k].max(self.timestamps[j]);
+            }
+        }
+
+        if matches!(duplicates, Duplicates::Mean) {
+            for (row, &count) in data.iter_mut().zip(&counts) {
+                row.2 /= count as f32;
+            }
+        }
+
+        let combined = self.data.len() - data.len();
+        if combined > 0 {
+            eprintln!("Combined {combined} duplicate rows");
+        }
+        self.data = data;
+        self.timestamps = timestamps;
+        Ok(())
+    }
+
+    pub fn problems(&self) -> Problems {
+        let empty_user = self.user_ids.iter().position(|id| id.is_empty());
+        let empty_item = self.item_ids.iter().position(|id| id.is_empty());
+
+        let mut problems = Problems::default();
+        let mut pairs = Vec::with_capacity(self.data.len());
+        for &(u, i, v) in &self.data {
+            if empty_user == Some(u as usize) || empty_item == Some(i as usize) {
+                problems.empty_ids += 1;
+            }
+            if !v.is_finite() {
+                problems.non_finite_values += 1;
+            }
+            pairs.push(pair_key(u, i));
+        }
+        pairs.sort_unstable();
+        pairs.dedup();
+        problems.duplicate_pairs = self.data.len() - pairs.len();
+        problems
+    }
+}
+
+// assigns dense indices to ids in order of appearance
+#[derive(Default)]
+struct Interner {
+    map: HashMap<String, u32>,
+}
+
+impl Interner {
+    fn intern(&mut self, id: &str) -> Result<u32, Box<dyn Error>> {
+        // only allocate for new ids
+        if let Some(&index) = self.map.get(id) {
+            return Ok(index);
+        }
+        let index = u32::try_from(self.map.len()).map_err(|_| "Too many ids")?;
+        self.map.insert(id.to_string(), index);
+        Ok(index)
+    }
+
+    fn into_ids(self) -> Vec<String> {
+        let mut ids = vec![String::new(); self.map.len()];
+        for (id, index) in self.map {
+            ids[index as usize] = id;
+        }
+        ids
+    }
+}
+
+// interns ids while adding rows
+struct InteractionsBuilder {
+    users: Interner,
+    items: Interner,
+    data: Vec<Row>,
+    timestamps: Vec<i64>,
+    started_at: Instant,
+}
+
+impl InteractionsBuilder {
+    fn new() -> Self {
+        Self {
+            users: Interner::default(),
+            items: Interner::default(),
+            data: Vec::new(),
+            timestamps: Vec::new(),
+            started_at: Instant::now(),
+        }
+    }
+
+    fn push(&mut self, user_id: &str, item_id: &str, value: f32) -> Result<(), Box<dyn Error>> {
+        self.data.push((
+            self.users.intern(user_id)?,
+            self.items.intern(item_id)?,
+            value,
+        ));
+        Ok(())
+    }
+
+    fn finish(self, explicit: bool) -> Interactions {
+        let elapsed = self.started_at.elapsed().as_secs_f64();
+        let mut message = format!(
+            "Read {} rows in {:.1}s ({:.0} rows/s",
+            self.data.len(),
+            elapsed,
+            self.data.len() as f64 / elapsed.max(f64::EPSILON)
+        );
+        if let Some(bytes) = peak_memory() {
+            message.push_str(&format!(", peak memory: {} MB", bytes / 1_000_000));
+        }
+        eprintln!("{message})");
+
+        Interactions {
+            data: self.data,
+            user_ids: self.users.into_ids(),
+            item_ids: self.items.into_ids(),
+            explicit,
+            timestamps: self.timestamps,
+        }
+    }
+}
+
+// indices of the columns to read
+struct Columns {
+    user: usize,
+    item: usize,
+    // rows are counted without a value
+    value: Option<usize>,
+    value_name: String,
+    timestamp: Option<usize>,
+    explicit: bool,
+}
+
+impl Columns {
+    fn new(
+        headers: Option<&StringRecord>,
+        len: usize,
+        options: &InputOptions,
+        timestamp_column: Option<&str>,
+    ) -> Result<Columns, Box<dyn Error>> {
+        let no_header = headers.is_none();
+        let user_column =
+            options
+                .user_column
+                .as_deref()
+                .unwrap_or(if no_header { "0" } else { "user_id" });
+        let item_column =
+            options
+                .item_column
+                .as_deref()
+                .unwrap_or(if no_header { "1" } else { "item_id" });
+
+        let user = column_index(headers, len, user_column)?;
+        let item = column_index(headers, len, item_column)?;
+        let counting = matches!(options.duplicates, Some(Duplicates::Count));
+        let value = match &options.value_column {
+            Some(_) if counting => {
+                return Err("Cannot use --value-column with --duplicates count".into());
+            }
+            // values are ignored when counting
+            _ if counting => None,
+            Some(column) => Some(column_index(headers, len, column)?),
+            None if no_header => Some(column_index(None, len, "2")?),
+            None => {
+                // prefer the column that matches the feedback
+                let candidates = match options.feedback {
+                    Feedback::Explicit => ["rating", "value"],
+                    Feedback::Auto | Feedback::Implicit => ["value", "rating"],
+                };
+                let index = candidates
+                    .iter()
+                    .find_map(|c| column_index(headers, len, c).ok())
+                    .ok_or("Missing rating/value column")?;
+                Some(index)
+            }
+        };
+        let timestamp = match timestamp_column {
+            Some(column) => Some(column_index(headers, len, column)?),
+            // keep the latest row when the default column is present
+            None if matches!(options.duplicates, Some(Duplicates::Last)) => {
+                headers.and_then(|h| h.iter().position(|r| r == "timestamp"))
+            }
+            None => None,
+        };
+
+        // infer from the name of the value column
+        let explicit = match options.feedback {
+            Feedback::Auto => {
+                value.is_some_and(|v| headers.is_none_or(|h| h.get(v) != Some("value")))
+            }
+            Feedback::Explicit => true,
+            Feedback::Implicit => false,
+        };
+
+        Ok(Columns {
+            user,
+            item,
+            value,
+            value_name: value.map(|v| column_name(headers, v)).unwrap_or_default(),
+            timestamp,
+            explicit,
+        })
+    }
+}
+
+// a header name or a zero-based index
+fn column_index(
+    headers: Option<&StringRecord>,
+    len: usize,
+    column: &str,
+) -> Result<usize, Box<dyn Error>> {
+    if let Some(index) = headers.and_then(|h| h.iter().position(|r| r == column)) {
+        return Ok(index);
+    }
+
+    match column.parse::<usize>() {
+        Ok(index) if index < len => Ok(index),
+        Ok(index) => Err(format!("Missing column {index}").into()),
+        Err(_) if headers.is_none() => {
+            Err(format!("Invalid column: {column}. Use an index with --no-header.").into())
+        }
+        Err(_) => Err(format!("Missing {column} column").into()),
+    }
+}
+
+pub fn read_interactions(
+    input: &Path,
+    options: &InputOptions,
+    require_timestamps: bool,
+) -> Result<Interactions, Box<dyn Error>> {
+    let interactions = read_unchecked(input, options, require_timestamps)?;
+    if options.strict {
+        check_strict(&interactions.problems())?;
+    }
+    Ok(interactions)
+}
+
+pub fn check_strict(problems: &Problems) -> Result<(), Box<dyn Error>> {
+    let messages = problems.messages();
+    if !messages.is_empty() {
+        return Err(format!(
+            "Invalid input ({}). Use disco inspect for details.",
+            messages.join(", ")
+        )
+        .into());
+    }
+    Ok(())
+}
+
+pub fn read_unchecked(
+    input: &Path,
+    options: &InputOptions,
+    require_timestamps: bool,
+) -> Result<Interactions, Box<dyn Error>> {
+    let timestamp_column = options.timestamp_column(require_timestamps);
+    let format = match &options.input_format {
+        Some(format) => format.clone(),
+        None if is_parquet(input) => InputFormat::Parquet,
+        None => InputFormat::Csv,
+    };
+    let mut interactions = match format {
+        InputFormat::Csv => read_csv(input, options, timestamp_column)?,
+        #[cfg(feature = "parquet")]
+        InputFormat::Parquet => read_parquet(input, options, timestamp_column)?,
+        #[cfg(not(feature = "parquet"))]
+        InputFormat::Parquet => return Err(parquet_not_enabled()),
+    };
+    let mut records = None;
+    if options.since.is_some() || options.until.is_some() {
+        records = Some(interactions.filter_time(options.since, options.until));
+    }
+    // before combining duplicates so each interaction decays
+    if let Some(half_life) = options.half_life {
+        interactions.decay(half_life)?;
+    }
+    if let Some(duplicates) = &options.duplicates {
+        interactions.aggregate(duplicates, records.as_deref())?;
+    }
+    // on the combined values and before transforming
+    if let Some(min_value) = options.min_value {
+        interactions.filter_value(min_value)?;
+    }
+    if let Some(transform) = &options.value_transform {
+        interactions.transform(transform)?;
+    }
+    Ok(interactions)
+}
+
+fn read_csv(
+    input: &Path,
+    options: &InputOptions,
+    timestamp_column: Option<&str>,
+) -> Result<Interactions, Box<dyn Error>> {
+    let compression = match &options.compression {
+        Some(compression) => compression.clone(),
+        None => Compression::from_path(input),
+    };
+    let file = open_compressed(input, &compression)?;
+    let mut rdr = csv::ReaderBuilder::new()
+        .has_headers(!options.no_header)
+        .from_reader(file);
+
+    let headers = if options.no_header {
+        None
+    } else {
+        Some(rdr.headers()?.clone())
+    };
+    // without headers, use the first record for the number of columns
+    let len = match &headers {
+        Some(h) => h.len(),
+        None => rdr.headers()?.len(),
+    };
+    let columns = Columns::new(headers.as_ref(), len, options, timestamp_column)?;
+
+    let mut builder = InteractionsBuilder::new();
+
+    // reuse the same record to avoid allocating for each row
+    let mut record = ByteRecord::new();
+    let mut i = 0;
+    while rdr.read_byte_record(&mut record)? {
+        // line number of first record depends on header
+        let line = if options.no_header { i + 1 } else { i + 2 };
+        let field = |index: usize| -> Result<&str, Box<dyn Error>> {
+            // safe to unwrap since csv::Reader checks for same number of columns as header
+            str::from_utf8(record.get(index).unwrap())
+                .map_err(|e| input_error(i, line, &column_name(headers.as_ref(), index), e).into())
+        };
+
+        let value = match columns.value {
+            Some(index) => field(index)?
+                .parse::<f32>()
+                .map_err(|e| input_error(i, line, &columns.value_name, e))?,
+            None => 1.0,
+        };
+        builder.push(field(columns.user)?, field(columns.item)?, value)?;
+
+        if let Some(index) = columns.timestamp {
+            builder.timestamps.push(
+                field(index)?
+                    .parse::<i64>()
+                    .map_err(|e| input_error(i, line, &column_name(headers.as_ref(), index), e))?,
+            );
+        }
+
+        i += 1;
+    }
+
+    Ok(builder.finish(columns.explicit))
+}
+
+#[cfg(feature = "parquet")]
+fn read_parquet(
+    input: &Path,
+    options: &InputOptions,
+    timestamp_column: Option<&str>,
+) -> Result<Interactions, Box<dyn Error>> {
+    use crate::helpers::{is_stdio, open_file};
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+
+    // footer is read first
+    if is_stdio(input) {
+        return Err("Parquet input from stdin not supported".into());
+    }
+    let reader = SerializedFileReader::new(open_file(input)?)?;
+
+    // only top-level columns are supported
+    let schema = reader.metadata().file_metadata().schema_descr();
+    let leaves: Vec<usize> = (0..schema.num_columns())
+        .filter(|&i| schema.column(i).path().parts().len() == 1)
+        .collect();
+    let headers: StringRecord = leaves
+        .iter()
+        .map(|&i| schema.column(i).name().to_string())
+        .collect();
+    let columns = Columns::new(Some(&headers), headers.len(), options, timestamp_column)?;
+
+    let mut builder = InteractionsBuilder::new();
+
+    // reuse the same buffers to avoid allocating for each integer id
+    let mut user_buf = String::new();
+    let mut item_buf = String::new();
+
+    // read a column chunk at a time instead of a row at a time
+    let mut offset = 0;
+    for r in 0..reader.num_row_groups() {
+        let row_group = reader.get_row_group(r)?;
+        let rows = row_group.metadata().num_rows() as usize;
+        let read = |index: usize| {
+            let name = column_name(Some(&headers), index);
+            ParquetColumn::read(row_group.as_ref(), leaves[index], rows, offset, &name)
+        };
+
+        let users = read(columns.user)?;
+        let items = read(columns.item)?;
+        let values = columns.value.map(read).transpose()?;
+        let timestamps = columns.timestamp.map(read).transpose()?;
+
+        for j in 0..rows {
+            let error =
+                |index: usize| parquet_error(offset + j, &column_name(Some(&headers), index));
+
+            let user_id = users
+                .string(j, &mut user_buf)
+                .ok_or_else(|| error(columns.user))?;
+            let item_id = items
+                .string(j, &mut item_buf)
+                .ok_or_else(|| error(columns.item))?;
+            let value = match (&values, columns.value) {
+                (Some(values), Some(index)) => values.number(j).ok_or_else(|| error(index))? as f32,
+                _ => 1.0,
+            };
+            builder.push(user_id, item_id, value)?;
+
+            if let (Some(timestamps), Some(index)) = (&timestamps, columns.timestamp) {
+                let timestamp = timestamps.timestamp(j).ok_or_else(|| error(index))?;
+                builder.timestamps.push(timestamp);
+            }
+        }
+
+        offset += rows;
+    }
+
+    Ok(builder.finish(columns.explicit))
+}
+
+// non-null values of a column chunk
+#[cfg(feature = "parquet")]
+fn read_parquet_values<T: parquet::data_type::DataType>(
+    reader: &mut parquet::column::reader::ColumnReaderImpl<T>,
+    rows: usize,
+    def_levels: &mut Vec<i16>,
+) -> parquet::errors::Result<Vec<T::T>> {
+    let mut values = Vec::with_capacity(rows);
+    reader.read_records(rows, Some(def_levels), None, &mut values)?;
+    Ok(values)
+}
+
+// values of a column chunk by how they can be used
+#[cfg(feature = "parquet")]
+enum ParquetColumn {
+    Bool(Vec<bool>),
+    Int(Vec<i64>),
+    UInt(Vec<u64>),
+    Float(Vec<f64>),
+    Bytes(Vec<parquet::data_type::ByteArray>),
+    // Unix timestamps in seconds
+    Timestamp(Vec<i64>),
+    Unsupported,
+}
+
+#[cfg(feature = "parquet")]
+impl ParquetColumn {
+    fn read(
+        row_group: &dyn parquet::file::reader::RowGroupReader,
+        index: usize,
+        rows: usize,
+        offset: usize,
+        name: &str,
+    ) -> Result<ParquetColumn, Box<dyn Error>> {
+        use parquet::basic::{ConvertedType, LogicalType, TimeUnit};
+        use parquet::column::reader::ColumnReader;
+
+        let descr = row_group.metadata().column(index).column_descr();
+        let converted_type = descr.converted_type();
+        let mut def_levels = Vec::with_capacity(rows);
+
+        let column = match row_group.get_column_reader(index)? {
+            ColumnReader::BoolColumnReader(mut reader) => {
+                let v = read_parquet_values(&mut reader, rows, &mut def_levels)?;
+                ParquetColumn::Bool(v)
+            }
+            ColumnReader::Int32ColumnReader(mut reader) => {
+                let v = read_parquet_values(&mut reader, rows, &mut def_levels)?;
+                match converted_type {
+                    ConvertedType::NONE
+                    | ConvertedType::INT_8
+                    | ConvertedType::INT_16
+                    | ConvertedType::INT_32 => {
+                        ParquetColumn::Int(v.into_iter().map(|v| v as i64).collect())
+                    }
+                    ConvertedType::UINT_8 | ConvertedType::UINT_16 | ConvertedType::UINT_32 => {
+                        ParquetColumn::Int(v.into_iter().map(|v| v as u32 as i64).collect())
+                    }
+                    _ => ParquetColumn::Unsupported,
+                }
+            }
+            ColumnReader::Int64ColumnReader(mut reader) => {
+                let v = read_parquet_values(&mut reader, rows, &mut def_levels)?;
+                // nanoseconds only have a logical type
+                let logical_type = descr.logical_type();
+                let units_per_second = match (&logical_type, converted_type) {
+                    (Some(LogicalType::Timestamp { unit, .. }), _) => Some(match unit {
+                        TimeUnit::MILLIS(_) => 1_000,
+                        TimeUnit::MICROS(_) => 1_000_000,
+                        TimeUnit::NANOS(_) => 1_000_000_000,
+                    }),
+                    (_, ConvertedType::TIMESTAMP_MILLIS) => Some(1_000),
+                    (_, ConvertedType::TIMESTAMP_MICROS) => Some(1_000_000),
+                    _ => None,
+                };
+                let time = matches!(logical_type, Some(LogicalType::Time { .. }));
+                match (units_per_second, converted_type) {
+                    (Some(units), _) => ParquetColumn::Timestamp(
+                        v.into_iter().map(|v| v.div_euclid(units)).collect(),
+                    ),
+                    (None, ConvertedType::NONE | ConvertedType::INT_64) if !time => {
+                        ParquetColumn::Int(v)
+                    }
+                    (None, ConvertedType::UINT_64) => {
+                        ParquetColumn::UInt(v.into_iter().map(|v| v as u64).collect())
+                    }
+                    _ => ParquetColumn::Unsupported,
+                }
+            }
+            ColumnReader::Int96ColumnReader(mut reader) => {
+                let v = read_parquet_values(&mut reader, rows, &mut def_levels)?;
+                ParquetColumn::Timestamp(v.iter().map(|v| v.to_seconds_and_nanos().0).collect())
+            }
+            ColumnReader::FloatColumnReader(mut reader) => {
+                let v = read_parquet_values(&mut reader, rows, &mut def_levels)?;
+                ParquetColumn::Float(v.into_iter().map(|v| v as f64).collect())
+            }
+            ColumnReader::DoubleColumnReader(mut reader) => {
+                let v = read_parquet_values(&mut reader, rows, &mut def_levels)?;
+                ParquetColumn::Float(v)
+            }
+            ColumnReader::ByteArrayColumnReader(mut reader) => {
+                let v = read_parquet_values(&mut reader, rows, &mut def_levels)?;
+                match converted_type {
+                    ConvertedType::NONE
+                    | ConvertedType::UTF8
+                    | ConvertedType::ENUM
+                    | ConvertedType::JSON
+                    | ConvertedType::BSON => ParquetColumn::Bytes(v),
+                    _ => ParquetColumn::Unsupported,
+                }
+            }
+            ColumnReader::FixedLenByteArrayColumnReader(_) => ParquetColumn::Unsupported,
+        };
+
+        // values only include non-null values
+        let max_def_level = descr.max_def_level();
+        if let Some(j) = def_levels.iter().position(|&v| v < max_def_level) {
+            return Err(parquet_error(offset + j, name).into());
+        }
+
+        Ok(column)
+    }
+
+    // integers are formatted into the buffer
+    fn string<'a>(&'a self, j: usize, buf: &'a mut String) -> Option<&'a str> {
+        use std::fmt::Write;
+
+        buf.clear();
+        match self {
+            ParquetColumn::Bytes(v) => return v[j].as_utf8().ok(),
+            ParquetColumn::Int(v) => write!(buf, "{}", v[j]).unwrap(),
+            ParquetColumn::UInt(v) => write!(buf, "{}", v[j]).unwrap(),
+            _ => return None,
+        }
+        Some(buf)
+    }
+
+    fn number(&self, j: usize) -> Option<f64> {
+        match self {
+            ParquetColumn::Bool(v) => Some(v[j] as u8 as f64),
+            ParquetColumn::Int(v) => Some(v[j] as f64),
+            ParquetColumn::UInt(v) => Some(v[j] as f64),
+            ParquetColumn::Float(v) => Some(v[j]),
+            _ => None,
+        }
+    }
+
+    fn timestamp(&self, j: usize) -> Option<i64> {
+        match self {
+            ParquetColumn::Timestamp(v) => Some(v[j]),
+            ParquetColumn::Int(v) => Some(v[j]),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "parquet")]
+fn parquet_error(i: usize, column: &str) -> String {
+    format!(
+        "Input error: record {} (column: {}): unsupported type or null",
+        i + 1,
+        column
+    )
+}
+
+fn column_name(headers: Option<&StringRecord>, index: usize) -> String {
+    match headers {
+        Some(h) => h.get(index).unwrap().to_string(),
+        None => index.to_string(),
+    }
+}
+
+// match CSV error: record 1 (line: 2, byte: 23): found record with 2 fields
+fn input_error(i: usize, line: usize, column: &str, e: impl std::fmt::Display) -> String {
+    format!(
+        "Input error: record {} (line: {}, column: {}): {}",
+        i + 1,
+        line,
+        column,
+        e
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn interactions(data: Vec<Row>, timestamps: Vec<i64>) -> Interactions {
+        Interactions {
+            data,
+            user_ids: vec!["u1".to_string(), "u2".to_string()],
+            item_ids: vec!["i1".to_string(), "i2".to_string()],
+            explicit: false,
+            timestamps,
+        }
+    }
+
+    #[test]
+    fn test_bm25() {
+        let mut interactions = Interactions {
+            data: vec![
+                (0, 0, 1.0),
+                (1, 0, 1.0),
+                (1, 1, 1.0),
+                (1, 2, 1.0),
+                (1, 3, 1.0),
+            ],
+            user_ids: vec!["u1".to_string(), "u2".to_string()],
+            item_ids: (1..=4).map(|i| format!("i{i}")).collect(),
+            explicit: false,
+            timestamps: Vec::new(),
+        };
+        interactions.bm25();
+        // idf = ln(4) - ln(2) and length_norm = 0.2 + 0.8 * 2 / 1.25
+        let expected = 101.0 / 149.0 * 2.0f32.ln();
+        assert!((interactions.data[0].2 - expected).abs() < 1e-6);
+        // negative idf is clamped
+        for row in &interactions.data[1..] {
+            assert_eq!(0.0, row.2);
+        }
+    }
+
+    #[test]
+    fn test_bm25_zero() {
+        let mut interactions = interactions(vec![(0, 0, 0.0), (1, 1, -1.0)], Vec::new());
+        interactions.bm25();
+        assert_eq!(vec![(0, 0, 0.0), (1, 1, 0.0)], interactions.data);
+    }
+
+    #[test]
+    fn test_filter_time() {
+        let mut interactions = interactions(
+            vec![(0, 0, 1.0), (1, 1, 2.0), (0, 1, 3.0)],
+            vec![50, 100, 300],
+        );
+        let records = interactions.filter_time(Some(100), Some(300));
+        assert_eq!(vec![1], records);
+        assert_eq!(vec![(0, 0, 2.0)], interactions.data);
+        assert_eq!(vec![100], interactions.timestamps);
+        assert_eq!(vec!["u2"], interactions.user_ids);
+        assert_eq!(vec!["i2"], interactions.item_ids);
+    }
+
+    #[test]
+    fn test_filter_time_since() {
+        let mut interactions = interactions(
+            vec![(0, 0, 1.0), (1, 1, 2.0), (0, 1, 3.0)],
+            vec![50, 100, 300],
+        );
+        let records = interactions.filter_time(Some(100), None);
+        assert_eq!(vec![1, 2], records);
+        assert_eq!(vec![(1, 0, 2.0), (0, 0, 3.0)], interactions.data);
+        assert_eq!(vec!["u1", "u2"], interactions.user_ids);
+        assert_eq!(vec!["i2"], interactions.item_ids);
+    }
+
+    #[test]
+    fn test_decay() {
+        let mut interactions = interactions(
+            vec![(0, 0, 4.0), (1, 1, 4.0), (0, 1, 4.0)],
+            vec![100, 200, 300],
+        );
+        interactions.decay(100.0).unwrap();
+        let values: Vec<f32> = interactions.data.iter().map(|v| v.2).collect();
+        assert_eq!(vec![1.0, 2.0, 4.0], values);
+    }
+
+    #[test]
+    fn test_decay_explicit() {
+        let mut interactions = interactions(vec![(0, 0, 4.0)], vec![100]);
+        interactions.explicit = true;
+        assert_eq!(
+            "--half-life requires implicit feedback",
+            interactions.decay(100.0).unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn test_decay_count() {
+        // values are 1 when counting
+        let mut interactions = interactions(
+            vec![(0, 0, 1.0), (0, 0, 1.0), (1, 1, 1.0)],
+            vec![100, 200, 300],
+        );
+        interactions.decay(100.0).unwrap();
+        interactions.aggregate(&Duplicates::Count, None).unwrap();
+        assert_eq!(vec![(0, 0, 0.75), (1, 1, 1.0)], interactions.data);
+        assert_eq!(vec![200, 300], interactions.timestamps);
+    }
+
+    #[test]
+    fn test_filter_support_empty() {
+        let mut interactions = interactions(vec![(0, 0, 1.0), (1, 1, 1.0)], Vec::new());
+        assert!(interactions.filter_support(2, 1, false).is_err());
+    }
+
+    #[test]
+    fn test_filter_value_empty() {
+        let mut interactions = interactions(vec![(0, 0, 1.0), (1, 1, 2.0)], Vec::new());
+        assert!(interactions.filter_value(3.0).is_err());
+    }
+
+    fn input_options(args: &[&str]) -> InputOptions {
+        use clap::{Command, FromArgMatches};
+
+        let command = InputOptions::augment_args(Command::new("disco"));
+        let matches =
+            command.get_matches_from(std::iter::once("disco").chain(args.iter().copied()));
+        InputOptions::from_arg_matches(&matches).unwrap()
+    }
+
+    #[test]
+    fn test_columns_last_timestamp() {
+        // the default column is used by last when present
+        let headers = StringRecord::from(vec!["user_id", "item_id", "rating", "timestamp"]);
+        let options = input_options(&["--duplicates", "last"]);
+        let columns = Columns::new(Some(&headers), headers.len(), &options, None).unwrap();
+        assert_eq!(Some(3), columns.timestamp);
+
+        let options = input_options(&["--duplicates", "max"]);
+        let columns = Columns::new(Some(&headers), headers.len(), &options, None).unwrap();
+        assert_eq!(None, columns.timestamp);
+
+        let headers = StringRecord::from(vec!["user_id", "item_id", "rating"]);
+        let options = input_options(&["--duplicates", "last"]);
+        let columns = Columns::new(Some(&headers), headers.len(), &options, None).unwrap();
+        assert_eq!(None, columns.timestamp);
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn test_parquet_timestamp_nanos() {
+        use parquet::basic::{LogicalType, Repetition, TimeUnit, Type as PhysicalType};
+        use parquet::data_type::Int64Type;
+        use parquet::file::reader::{FileReader, SerializedFileReader};
+        use parquet::file::writer::SerializedFileWriter;
+        use parquet::format::NanoSeconds;
+        use parquet::schema::types::Type;
+        use std::sync::Arc;
+
+        let path = std::env::temp_dir().join(format!("disco-{}-nanos.parquet", std::process::id()));
+        let field = Type::primitive_type_builder("timestamp", PhysicalType::INT64)
+            .with_logical_type(Some(LogicalType::Timestamp {
+                is_adjusted_to_u_t_c: true,
+                unit: TimeUnit::NANOS(NanoSeconds {}),
+            }))
+            .with_repetition(Repetition::REQUIRED)
+            .build()
+            .unwrap();
+        let schema = Type::group_type_builder("schema")
+            .with_fields(vec![Arc::new(field)])
+            .build()
+            .unwrap();
+        let file = std::fs::File::create(&path).unwrap();
+        let mut writer =
+            SerializedFileWriter::new(file, Arc::new(schema), Default::default()).unwrap();
+        let mut row_group = writer.next_row_group().unwrap();
+        let mut column = row_group.next_column().unwrap().unwrap();
+        column
+            .typed::<Int64Type>()
+            .write_batch(&[1_500_000_000_123_456_789, -1], None, None)
+            .unwrap();
+        column.close().unwrap();
+        row_group.close().unwrap();
+        writer.close().unwrap();
+
+        let reader = SerializedFileReader::new(std::fs::File::open(&path).unwrap()).unwrap();
+        let column = ParquetColumn::read(
+            reader.get_row_group(0).unwrap().as_ref(),
+            0,
+            2,
+            0,
+            "timestamp",
+        );
+        std::fs::remove_file(&path).unwrap();
+        match column.unwrap() {
+            ParquetColumn::Timestamp(v) => assert_eq!(vec![1_500_000_000, -1], v),
+            _ => panic!("expected timestamps"),
+        }
+    }
+}
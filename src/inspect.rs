@@ -0,0 +1,86 @@
+use crate::input::{InputOptions, check_strict, read_unchecked};
+use std::error::Error;
+use std::path::Path;
+
+// show counts for each value up to this many distinct values
+const MAX_DISTINCT_VALUES: usize = 10;
+
+fn percentile(sorted: &[f32], p: f64) -> f32 {
+    sorted[((sorted.len() - 1) as f64 * p).round() as usize]
+}
+
+fn percent(count: usize, total: usize) -> f64 {
+    100.0 * count as f64 / total.max(1) as f64
+}
+
+pub fn inspect(input: &Path, input_options: &InputOptions) -> Result<(), Box<dyn Error>> {
+    let interactions = read_unchecked(input, input_options, false)?;
+    let problems = interactions.problems();
+
+    let rows = interactions.data.len();
+    let users = interactions.user_ids.len();
+    let items = interactions.item_ids.len();
+    let pairs = rows - problems.duplicate_pairs;
+    let cells = users * items;
+
+    println!("Rows: {rows}");
+    println!("Users: {users}");
+    println!("Items: {items}");
+    if cells > 0 {
+        println!("Sparsity: {:.4}%", 100.0 - percent(pairs, cells));
+    }
+    println!(
+        "Feedback: {}",
+        if interactions.explicit {
+            "explicit"
+        } else {
+            "implicit"
+        }
+    );
+
+    let mut values: Vec<f32> = interactions
+        .data
+        .iter()
+        .map(|v| v.2)
+        .filter(|v| v.is_finite())
+        .collect();
+    values.sort_unstable_by(|a, b| a.total_cmp(b));
+    if !values.is_empty() {
+        let mean = values.iter().map(|&v| v as f64).sum::<f64>() / values.len() as f64;
+        println!(
+            "Values: min {}, p25 {}, median {}, p75 {}, max {}, mean {:.4}",
+            values[0],
+            percentile(&values, 0.25),
+            percentile(&values, 0.5),
+            percentile(&values, 0.75),
+            values[values.len() - 1],
+            mean
+        );
+
+        // values are sorted, so equal values are adjacent
+        let counts: Vec<(f32, usize)> = values
+            .chunk_by(|a, b| a == b)
+            .map(|c| (c[0], c.len()))
+            .collect();
+        if counts.len() <= MAX_DISTINCT_VALUES {
+            for (value, count) in counts {
+                println!(
+                    "  {}: {} ({:.2}%)",
+                    value,
+                    count,
+                    percent(count, values.len())
+                );
+            }
+        }
+    }
+
+    println!("Duplicate pairs: {}", problems.duplicate_pairs);
+    println!("Empty ids: {}", problems.empty_ids);
+    println!("Non-finite values: {}", problems.non_finite_values);
+
+    if input_options.strict() {
+        check_strict(&problems)?;
+    }
+
+    Ok(())
+}
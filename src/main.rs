@@ -1,11 +1,34 @@
+mod ann;
+mod compression;
 mod download;
+mod evaluate;
+mod export;
 mod helpers;
+mod input;
+mod inspect;
+mod model;
+mod output;
+mod predict;
+mod prng;
+mod recommend;
 mod recs;
+mod train;
+mod tune;
 
+use ann::AnnOptions;
 use download::*;
+use evaluate::*;
+use export::*;
+use input::InputOptions;
+use inspect::inspect;
+use output::RecsFormat;
+use predict::*;
+use recommend::*;
 use recs::*;
+use train::*;
+use tune::*;
 
-use clap::builder::{PossibleValuesParser, TypedValueParser};
+use clap::builder::{PossibleValuesParser, RangedU64ValueParser, TypedValueParser};
 use clap::{Parser, Subcommand};
 use std::path::PathBuf;
 use std::process;
@@ -20,61 +43,213 @@ struct Args {
 #[derive(Debug, Subcommand)]
 enum Commands {
     /// Generate user-based recommendations
+    #[command(allow_missing_positional = true, mut_arg("model", |a| a.conflicts_with("InputOptions")))]
     UserRecs {
-        #[arg(value_parser)]
-        input: PathBuf,
+        #[command(flatten)]
+        model_options: ModelOptions,
 
         #[arg(value_parser)]
         output: PathBuf,
 
-        #[arg(long, default_value_t = 10)]
+        #[arg(long, default_value_t = 10, value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
         count: usize,
 
-        #[arg(long, default_value_t = 8)]
-        factors: u32,
+        #[command(flatten)]
+        selection: UserSelection,
+
+        #[command(flatten)]
+        filter_options: UserFilterOptions,
+
+        #[arg(long, value_parser = PossibleValuesParser::new(RecsFormat::variants()).map(|s| s.parse::<RecsFormat>().unwrap()))]
+        format: Option<RecsFormat>,
 
-        #[arg(long, default_value_t = 20)]
-        iterations: u32,
+        #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+        threads: u32,
 
         #[arg(long)]
         overwrite: bool,
     },
     /// Generate item-based recommendations
+    #[command(allow_missing_positional = true, mut_arg("model", |a| a.conflicts_with("InputOptions")))]
     ItemRecs {
-        #[arg(value_parser)]
-        input: PathBuf,
+        #[command(flatten)]
+        model_options: ModelOptions,
 
         #[arg(value_parser)]
         output: PathBuf,
 
-        #[arg(long, default_value_t = 10)]
+        #[arg(long, default_value_t = 10, value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
         count: usize,
 
-        #[arg(long, default_value_t = 8)]
-        factors: u32,
+        #[command(flatten)]
+        selection: ItemSelection,
+
+        #[command(flatten)]
+        filter_options: FilterOptions,
 
-        #[arg(long, default_value_t = 20)]
-        iterations: u32,
+        #[command(flatten)]
+        ann_options: AnnOptions,
+
+        #[arg(long, value_parser = PossibleValuesParser::new(RecsFormat::variants()).map(|s| s.parse::<RecsFormat>().unwrap()))]
+        format: Option<RecsFormat>,
+
+        #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+        threads: u32,
 
         #[arg(long)]
         overwrite: bool,
     },
     /// Generate similar users
+    #[command(allow_missing_positional = true, mut_arg("model", |a| a.conflicts_with("InputOptions")))]
     SimilarUsers {
+        #[command(flatten)]
+        model_options: ModelOptions,
+
+        #[arg(value_parser)]
+        output: PathBuf,
+
+        #[arg(long, default_value_t = 10, value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+        count: usize,
+
+        #[command(flatten)]
+        selection: UserSelection,
+
+        #[command(flatten)]
+        ann_options: AnnOptions,
+
+        #[arg(long, value_parser = PossibleValuesParser::new(RecsFormat::variants()).map(|s| s.parse::<RecsFormat>().unwrap()))]
+        format: Option<RecsFormat>,
+
+        #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+        threads: u32,
+
+        #[arg(long)]
+        overwrite: bool,
+    },
+    /// Generate recommendations for new users
+    #[command(allow_missing_positional = true)]
+    RecommendFor {
+        #[command(flatten)]
+        model_options: ModelOptions,
+
+        #[arg(value_parser)]
+        output: PathBuf,
+
+        #[arg(long, value_delimiter = ',', required_unless_present = "interactions")]
+        items: Vec<String>,
+
+        #[arg(long, conflicts_with = "items")]
+        interactions: Option<PathBuf>,
+
+        #[arg(long, default_value_t = 10, value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+        count: usize,
+
+        #[command(flatten)]
+        filter_options: FilterOptions,
+
+        #[arg(long)]
+        overwrite: bool,
+    },
+    /// Predict scores for user-item pairs
+    #[command(allow_missing_positional = true, mut_arg("model", |a| a.conflicts_with("InputOptions")))]
+    Predict {
+        #[command(flatten)]
+        model_options: ModelOptions,
+
+        #[arg(value_parser)]
+        output: PathBuf,
+
+        #[arg(long)]
+        pairs: PathBuf,
+
+        #[arg(long, default_value_t = Fallback::GlobalMean, value_parser = PossibleValuesParser::new(Fallback::variants()).map(|s| s.parse::<Fallback>().unwrap()))]
+        fallback: Fallback,
+
+        #[arg(long)]
+        overwrite: bool,
+    },
+    /// Train a model
+    Train {
+        #[arg(value_parser)]
+        input: PathBuf,
+
+        #[arg(value_parser)]
+        output: PathBuf,
+
+        #[command(flatten)]
+        input_options: InputOptions,
+
+        #[command(flatten)]
+        train_options: TrainOptions,
+
+        #[arg(long)]
+        overwrite: bool,
+    },
+    /// Evaluate a model on a holdout set
+    Evaluate {
+        #[arg(value_parser)]
+        input: PathBuf,
+
+        #[command(flatten)]
+        input_options: InputOptions,
+
+        #[command(flatten)]
+        split_options: SplitOptions,
+
+        #[command(flatten)]
+        train_options: TrainOptions,
+
+        #[arg(long, default_value_t = 10, value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+        count: usize,
+
+        #[arg(long)]
+        overwrite: bool,
+    },
+    /// Search for the best hyperparameters
+    Tune {
         #[arg(value_parser)]
         input: PathBuf,
 
         #[arg(value_parser)]
         output: PathBuf,
 
-        #[arg(long, default_value_t = 10)]
+        #[command(flatten)]
+        input_options: InputOptions,
+
+        #[command(flatten)]
+        split_options: SplitOptions,
+
+        #[command(flatten)]
+        search_options: SearchOptions,
+
+        #[arg(long, default_value_t = 10, value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
         count: usize,
 
-        #[arg(long, default_value_t = 8)]
-        factors: u32,
+        #[arg(long)]
+        overwrite: bool,
+    },
+    /// Summarize and validate input data
+    Inspect {
+        #[arg(value_parser)]
+        input: PathBuf,
+
+        #[command(flatten)]
+        input_options: InputOptions,
+    },
+    /// Export user and item factors
+    #[command(mut_arg("model", |a| a.conflicts_with("InputOptions")))]
+    ExportFactors {
+        #[command(flatten)]
+        model_options: ModelOptions,
 
-        #[arg(long, default_value_t = 20)]
-        iterations: u32,
+        #[arg(long, required_unless_present = "items")]
+        users: Option<PathBuf>,
+
+        #[arg(long)]
+        items: Option<PathBuf>,
+
+        #[arg(long, value_parser = PossibleValuesParser::new(ExportFormat::variants()).map(|s| s.parse::<ExportFormat>().unwrap()))]
+        format: Option<ExportFormat>,
 
         #[arg(long)]
         overwrite: bool,
@@ -97,29 +272,144 @@ fn main() {
 
     let res = match args.command {
         Commands::UserRecs {
-            input,
+            model_options,
             output,
             count,
-            factors,
-            iterations,
+            selection,
+            filter_options,
+            format,
+            threads,
             overwrite,
-        } => user_recs(&input, &output, count, factors, iterations, overwrite),
+        } => user_recs(
+            &model_options,
+            &output,
+            count,
+            &selection,
+            &filter_options,
+            format.as_ref(),
+            threads as usize,
+            overwrite,
+        ),
         Commands::ItemRecs {
-            input,
+            model_options,
             output,
             count,
-            factors,
-            iterations,
+            selection,
+            filter_options,
+            ann_options,
+            format,
+            threads,
             overwrite,
-        } => item_recs(&input, &output, count, factors, iterations, overwrite),
+        } => item_recs(
+            &model_options,
+            &output,
+            count,
+            &selection,
+            &filter_options,
+            &ann_options,
+            format.as_ref(),
+            threads as usize,
+            overwrite,
+        ),
         Commands::SimilarUsers {
+            model_options,
+            output,
+            count,
+            selection,
+            ann_options,
+            format,
+            threads,
+            overwrite,
+        } => similar_users(
+            &model_options,
+            &output,
+            count,
+            &selection,
+            &ann_options,
+            format.as_ref(),
+            threads as usize,
+            overwrite,
+        ),
+        Commands::RecommendFor {
+            model_options,
+            output,
+            items,
+            interactions,
+            count,
+            filter_options,
+            overwrite,
+        } => recommend_for(
+            &model_options,
+            &output,
+            &items,
+            interactions.as_deref(),
+            count,
+            &filter_options,
+            overwrite,
+        ),
+        Commands::Predict {
+            model_options,
+            output,
+            pairs,
+            fallback,
+            overwrite,
+        } => predict(&model_options, &pairs, &output, &fallback, overwrite),
+        Commands::Train {
             input,
             output,
+            input_options,
+            train_options,
+            overwrite,
+        } => train(&input, &output, &input_options, &train_options, overwrite),
+        Commands::Evaluate {
+            input,
+            input_options,
+            split_options,
+            train_options,
+            count,
+            overwrite,
+        } => evaluate(
+            &input,
+            &input_options,
+            &split_options,
+            &train_options,
             count,
-            factors,
-            iterations,
             overwrite,
-        } => similar_users(&input, &output, count, factors, iterations, overwrite),
+        ),
+        Commands::Tune {
+            input,
+            output,
+            input_options,
+            split_options,
+            search_options,
+            count,
+            overwrite,
+        } => tune(
+            &input,
+            &output,
+            &input_options,
+            &split_options,
+            &search_options,
+            count,
+            overwrite,
+        ),
+        Commands::Inspect {
+            input,
+            input_options,
+        } => inspect(&input, &input_options),
+        Commands::ExportFactors {
+            model_options,
+            users,
+            items,
+            format,
+            overwrite,
+        } => export_factors(
+            &model_options,
+            users.as_deref(),
+            items.as_deref(),
+            format.as_ref(),
+            overwrite,
+        ),
         Commands::Download {
             dataset,
             output,
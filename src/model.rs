@@ -0,0 +1,692 @@
+use discorec::Recommender;
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::{self, BufReader, BufWriter, ErrorKind, Read, Write};
+use std::path::Path;
+
+use crate::helpers::*;
+use crate::input::{Interactions, Row};
+
+const MAGIC: &[u8; 8] = b"DISCOMDL";
+const VERSION: u32 = 1;
+// limit allocations before the data is read for invalid files
+const MAX_PREALLOCATE: usize = 1 << 20;
+
+pub struct Model {
+    explicit: bool,
+    factors: usize,
+    global_mean: f32,
+    // used to fold in new users
+    regularization: f32,
+    alpha: f32,
+    user_ids: Vec<String>,
+    item_ids: Vec<String>,
+    user_map: HashMap<String, usize>,
+    item_map: HashMap<String, usize>,
+    user_factors: Vec<f32>,
+    item_factors: Vec<f32>,
+    // sorted item indices for each user
+    rated: Vec<Vec<u32>>,
+    user_norms: Vec<f32>,
+    item_norms: Vec<f32>,
+}
+
+impl Model {
+    pub fn new<'a>(
+        recommender: &Recommender<u32, u32>,
+        interactions: &Interactions,
+        factors: usize,
+        regularization: f32,
+        alpha: f32,
+        dataset: impl IntoIterator<Item = &'a Row>,
+    ) -> Model {
+        let user_ids: Vec<String> = recommender
+            .user_ids()
+            .iter()
+            .map(|&u| interactions.user_ids[u as usize].clone())
+            .collect();
+        let item_ids: Vec<String> = recommender
+            .item_ids()
+            .iter()
+            .map(|&i| interactions.item_ids[i as usize].clone())
+            .collect();
+
+        let mut user_factors = Vec::with_capacity(user_ids.len() * factors);
+        for user in recommender.user_ids() {
+            user_factors.extend_from_slice(recommender.user_factors(user).unwrap());
+        }
+
+        let mut item_factors = Vec::with_capacity(item_ids.len() * factors);
+        for item in recommender.item_ids() {
+            item_factors.extend_from_slice(recommender.item_factors(item).unwrap());
+        }
+
+        // from interned indices to model indices
+        let user_map = model_indices(recommender.user_ids(), interactions.user_ids.len());
+        let item_map = model_indices(recommender.item_ids(), interactions.item_ids.len());
+
+        // rated items from the dataset, which can include users and items
+        // that are only in the validation set and not in the model
+        let mut rated = vec![Vec::new(); user_ids.len()];
+        for &(user, item, _) in dataset {
+            let (u, i) = (user_map[user as usize], item_map[item as usize]);
+            if u != usize::MAX && i != usize::MAX {
+                rated[u].push(i as u32);
+            }
+        }
+
+        Model::from_parts(
+            interactions.explicit,
+            factors,
+            recommender.global_mean(),
+            regularization,
+            alpha,
+            user_ids,
+            item_ids,
+            user_factors,
+            item_factors,
+            rated,
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn from_parts(
+        explicit: bool,
+        factors: usize,
+        global_mean: f32,
+        regularization: f32,
+        alpha: f32,
+        user_ids: Vec<String>,
+        item_ids: Vec<String>,
+        user_factors: Vec<f32>,
+        item_factors: Vec<f32>,
+        mut rated: Vec<Vec<u32>>,
+    ) -> Model {
+        for items in &mut rated {
+            items.sort_unstable();
+            items.dedup();
+        }
+
+        let user_norms = norms(&user_factors, factors);
+        let item_norms = norms(&item_factors, factors);
+
+        Model {
+            explicit,
+            factors,
+            global_mean,
+            regularization,
+            alpha,
+            user_map: index_map(&user_ids),
+            item_map: index_map(&item_ids),
+            user_ids,
+            item_ids,
+            user_factors,
+            item_factors,
+            rated,
+            user_norms,
+            item_norms,
+        }
+    }
+
+    pub fn explicit(&self) -> bool {
+        self.explicit
+    }
+
+    pub fn has_user(&self, user_id: &str) -> bool {
+        self.user_map.contains_key(user_id)
+    }
+
+    pub fn has_item(&self, item_id: &str) -> bool {
+        self.item_map.contains_key(item_id)
+    }
+
+    pub fn user_ids(&self) -> &[String] {
+        &self.user_ids
+    }
+
+    pub fn item_ids(&self) -> &[String] {
+        &self.item_ids
+    }
+
+    pub fn factors(&self) -> usize {
+        self.factors
+    }
+
+    // row-major with one row per user
+    pub fn user_factors(&self) -> &[f32] {
+        &self.user_factors
+    }
+
+    // row-major with one row per item
+    pub fn item_factors(&self) -> &[f32] {
+        &self.item_factors
+    }
+
+    pub fn predict(&self, user_id: &str, item_id: &str) -> f32 {
+        match (self.user_map.get(user_id), self.item_map.get(item_id)) {
+            (Some(&u), Some(&i)) => dot(self.user_factor_row(u), self.item_factor_row(i)),
+            _ => self.global_mean,
+        }
+    }
+
+    pub fn user_index(&self, user_id: &str) -> Option<usize> {
+        self.user_map.get(user_id).copied()
+    }
+
+    pub fn item_index(&self, item_id: &str) -> Option<usize> {
+        self.item_map.get(item_id).copied()
+    }
+
+    pub fn user_recs(&self, user_id: &str, count: usize) -> Vec<(&String, f32)> {
+        self.user_recs_with(user_id, count, true, |_| true)
+    }
+
+    // filter is called with the index of each candidate item
+    pub fn user_recs_with<F>(
+        &self,
+        user_id: &str,
+        count: usize,
+        exclude_seen: bool,
+        filter: F,
+    ) -> Vec<(&String, f32)>
+    where
+        F: Fn(usize) -> bool,
+    {
+        let Some(&u) = self.user_map.get(user_id) else {
+            return Vec::new();
+        };
+
+        let rated = &self.rated[u];
+        self.recs_for_factors(self.user_factor_row(u), count, |i| {
+            (!exclude_seen || rated.binary_search(&(i as u32)).is_err()) && filter(i)
+        })
+    }
+
+    pub fn recs_for_factors<F>(
+        &self,
+        user_factors: &[f32],
+        count: usize,
+        filter: F,
+    ) -> Vec<(&String, f32)>
+    where
+        F: Fn(usize) -> bool,
+    {
+        let predictions = self
+            .item_factors
+            .chunks_exact(self.factors)
+            .map(|row| dot(row, user_factors));
+        top_n(predictions, count, filter)
+            .into_iter()
+            .map(|(i, score)| (&self.item_ids[i], score))
+            .collect()
+    }
+
+    // factors for a new user from (item index, value) pairs
+    // with the same objective as training, but solved exactly
+    pub fn fold_in(&self, interactions: &[(usize, f32)]) -> Vec<f32> {
+        let k = self.factors;
+        let mut a = vec![0.0; k * k];
+        let mut b = vec![0.0; k];
+
+        if self.explicit {
+            // minimize sum of (r - qu)^2 + lambda |u|^2
+            for &(i, value) in interactions {
+                let q = self.item_factor_row(i);
+                add_outer(&mut a, q, 1.0);
+                for (bj, qj) in b.iter_mut().zip(q) {
+                    *bj += value * qj;
+                }
+            }
+        } else {
+            // YtCuY = YtY + Yt(Cu - I)Y with preference of 1 for interacted items
+            for q in self.item_factors.chunks_exact(k) {
+                add_outer(&mut a, q, 1.0);
+            }
+            for &(i, value) in interactions {
+                let q = self.item_factor_row(i);
+                let confidence = 1.0 + self.alpha * value;
+                add_outer(&mut a, q, confidence - 1.0);
+                for (bj, qj) in b.iter_mut().zip(q) {
+                    *bj += confidence * qj;
+                }
+            }
+        }
+
+        for j in 0..k {
+            a[j * k + j] += self.regularization;
+        }
+
+        cholesky_solve(a, b, k)
+    }
+
+    pub fn item_recs_with<F>(&self, item_id: &str, count: usize, filter: F) -> Vec<(&String, f32)>
+    where
+        F: Fn(usize) -> bool,
+    {
+        let Some(&i) = self.item_map.get(item_id) else {
+            return Vec::new();
+        };
+
+        similar(
+            &self.item_factors,
+            &self.item_norms,
+            self.factors,
+            i,
+            count,
+            filter,
+        )
+        .into_iter()
+        .map(|(j, score)| (&self.item_ids[j], score))
+        .collect()
+    }
+
+    pub fn similar_users(&self, user_id: &str, count: usize) -> Vec<(&String, f32)> {
+        let Some(&u) = self.user_map.get(user_id) else {
+            return Vec::new();
+        };
+
+        similar(
+            &self.user_factors,
+            &self.user_norms,
+            self.factors,
+            u,
+            count,
+            |_| true,
+        )
+        .into_iter()
+        .map(|(v, score)| (&self.user_ids[v], score))
+        .collect()
+    }
+
+    fn user_factor_row(&self, u: usize) -> &[f32] {
+        &self.user_factors[(u * self.factors)..((u + 1) * self.factors)]
+    }
+
+    fn item_factor_row(&self, i: usize) -> &[f32] {
+        &self.item_factors[(i * self.factors)..((i + 1) * self.factors)]
+    }
+
+    pub fn save(&self, path: &Path, overwrite: bool) -> Result<(), Box<dyn Error>> {
+        let mut wtr = BufWriter::new(create_file(path, overwrite)?);
+
+        wtr.write_all(MAGIC)?;
+        write_u32(&mut wtr, VERSION)?;
+        wtr.write_all(&[self.explicit as u8])?;
+        write_u64(&mut wtr, self.factors as u64)?;
+        write_f32(&mut wtr, self.global_mean)?;
+        write_f32(&mut wtr, self.regularization)?;
+        write_f32(&mut wtr, self.alpha)?;
+
+        write_u64(&mut wtr, self.user_ids.len() as u64)?;
+        for user_id in &self.user_ids {
+            write_str(&mut wtr, user_id)?;
+        }
+
+        write_u64(&mut wtr, self.item_ids.len() as u64)?;
+        for item_id in &self.item_ids {
+            write_str(&mut wtr, item_id)?;
+        }
+
+        for v in self.user_factors.iter().chain(&self.item_factors) {
+            write_f32(&mut wtr, *v)?;
+        }
+
+        for items in &self.rated {
+            write_u64(&mut wtr, items.len() as u64)?;
+            for i in items {
+                write_u32(&mut wtr, *i)?;
+            }
+        }
+
+        wtr.flush()?;
+
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> Result<Model, Box<dyn Error>> {
+        let file = open_file(path)?;
+        let mut rdr = BufReader::new(file);
+
+        // truncated files are invalid
+        Model::read(&mut rdr, path).map_err(|e| match e.downcast_ref::<io::Error>() {
+            Some(e) if e.kind() == ErrorKind::UnexpectedEof => invalid_model(path),
+            _ => e,
+        })
+    }
+
+    fn read(rdr: &mut impl Read, path: &Path) -> Result<Model, Box<dyn Error>> {
+        let mut magic = [0; 8];
+        if rdr.read_exact(&mut magic).is_err() || &magic != MAGIC {
+            return Err(invalid_model(path));
+        }
+
+        let version = read_u32(rdr)?;
+        if version != VERSION {
+            return Err(format!("Unsupported model version: {version}").into());
+        }
+
+        let mut explicit = [0; 1];
+        rdr.read_exact(&mut explicit)?;
+        let explicit = explicit[0] != 0;
+        let factors = read_u64(rdr)? as usize;
+        if factors == 0 {
+            return Err(invalid_model(path));
+        }
+        let global_mean = read_f32(rdr)?;
+        let regularization = read_f32(rdr)?;
+        let alpha = read_f32(rdr)?;
+
+        let users = read_u64(rdr)? as usize;
+        let mut user_ids = Vec::with_capacity(users.min(MAX_PREALLOCATE));
+        for _ in 0..users {
+            user_ids.push(read_str(rdr).map_err(|_| invalid_model(path))?);
+        }
+
+        let items = read_u64(rdr)? as usize;
+        let mut item_ids = Vec::with_capacity(items.min(MAX_PREALLOCATE));
+        for _ in 0..items {
+            item_ids.push(read_str(rdr).map_err(|_| invalid_model(path))?);
+        }
+
+        let user_len = users
+            .checked_mul(factors)
+            .ok_or_else(|| invalid_model(path))?;
+        let mut user_factors = Vec::with_capacity(user_len.min(MAX_PREALLOCATE));
+        for _ in 0..user_len {
+            user_factors.push(read_f32(rdr)?);
+        }
+
+        let item_len = items
+            .checked_mul(factors)
+            .ok_or_else(|| invalid_model(path))?;
+        let mut item_factors = Vec::with_capacity(item_len.min(MAX_PREALLOCATE));
+        for _ in 0..item_len {
+            item_factors.push(read_f32(rdr)?);
+        }
+
+        let mut rated = Vec::with_capacity(users.min(MAX_PREALLOCATE));
+        for _ in 0..users {
+            let len = read_u64(rdr)? as usize;
+            let mut user_rated = Vec::with_capacity(len.min(MAX_PREALLOCATE));
+            for _ in 0..len {
+                let i = read_u32(rdr)?;
+                if i as usize >= items {
+                    return Err(invalid_model(path));
+                }
+                user_rated.push(i);
+            }
+            rated.push(user_rated);
+        }
+
+        Ok(Model::from_parts(
+            explicit,
+            factors,
+            global_mean,
+            regularization,
+            alpha,
+            user_ids,
+            item_ids,
+            user_factors,
+            item_factors,
+            rated,
+        ))
+    }
+}
+
+fn invalid_model(path: &Path) -> Box<dyn Error> {
+    format!("Invalid model: {}", path.display()).into()
+}
+
+fn index_map(ids: &[String]) -> HashMap<String, usize> {
+    ids.iter()
+        .enumerate()
+        .map(|(i, id)| (id.clone(), i))
+        .collect()
+}
+
+fn model_indices(ids: &[u32], len: usize) -> Vec<usize> {
+    let mut indices = vec![usize::MAX; len];
+    for (i, &id) in ids.iter().enumerate() {
+        indices[id as usize] = i;
+    }
+    indices
+}
+
+fn norms(factors: &[f32], cols: usize) -> Vec<f32> {
+    factors
+        .chunks_exact(cols)
+        .map(|row| row.iter().map(|v| v * v).sum::<f32>().sqrt())
+        .collect()
+}
+
+// a += weight * x * x^T
+fn add_outer(a: &mut [f32], x: &[f32], weight: f32) {
+    for (row, xi) in a.chunks_exact_mut(x.len()).zip(x) {
+        for (v, xj) in row.iter_mut().zip(x) {
+            *v += weight * xi * xj;
+        }
+    }
+}
+
+// solve Ax = b for a symmetric positive definite matrix
+fn cholesky_solve(mut a: Vec<f32>, mut b: Vec<f32>, n: usize) -> Vec<f32> {
+    // factor in place as lower triangular L with A = LL^T
+    for j in 0..n {
+        let mut d = a[j * n + j];
+        for k in 0..j {
+            d -= a[j * n + k] * a[j * n + k];
+        }
+        let d = d.max(f32::EPSILON).sqrt();
+        a[j * n + j] = d;
+        for i in (j + 1)..n {
+            let mut v = a[i * n + j];
+            for k in 0..j {
+                v -= a[i * n + k] * a[j * n + k];
+            }
+            a[i * n + j] = v / d;
+        }
+    }
+
+    // forward substitution with L
+    for i in 0..n {
+        for k in 0..i {
+            b[i] -= a[i * n + k] * b[k];
+        }
+        b[i] /= a[i * n + i];
+    }
+
+    // back substitution with L^T
+    for i in (0..n).rev() {
+        for k in (i + 1)..n {
+            b[i] -= a[k * n + i] * b[k];
+        }
+        b[i] /= a[i * n + i];
+    }
+
+    b
+}
+
+pub fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(ai, bi)| ai * bi).sum()
+}
+
+// same ordering as discorec
+fn top_n<I, F>(predictions: I, count: usize, filter: F) -> Vec<(usize, f32)>
+where
+    I: Iterator<Item = f32>,
+    F: Fn(usize) -> bool,
+{
+    let mut predictions: Vec<_> = predictions.enumerate().collect();
+    predictions.sort_by(|a, b| b.1.total_cmp(&a.1));
+    predictions
+        .into_iter()
+        .filter(|v| filter(v.0))
+        .take(count)
+        .collect()
+}
+
+fn similar<F>(
+    factors: &[f32],
+    norms: &[f32],
+    cols: usize,
+    i: usize,
+    count: usize,
+    filter: F,
+) -> Vec<(usize, f32)>
+where
+    F: Fn(usize) -> bool,
+{
+    let row = &factors[(i * cols)..((i + 1) * cols)];
+    let norm = norms[i];
+    let predictions = factors
+        .chunks_exact(cols)
+        .zip(norms)
+        .map(|(r, n)| dot(r, row) / (norm * n).max(f32::EPSILON));
+    top_n(predictions, count, |j| j != i && filter(j))
+}
+
+fn write_u32(wtr: &mut impl Write, v: u32) -> Result<(), Box<dyn Error>> {
+    wtr.write_all(&v.to_le_bytes())?;
+    Ok(())
+}
+
+fn write_u64(wtr: &mut impl Write, v: u64) -> Result<(), Box<dyn Error>> {
+    wtr.write_all(&v.to_le_bytes())?;
+    Ok(())
+}
+
+fn write_f32(wtr: &mut impl Write, v: f32) -> Result<(), Box<dyn Error>> {
+    wtr.write_all(&v.to_le_bytes())?;
+    Ok(())
+}
+
+fn write_str(wtr: &mut impl Write, v: &str) -> Result<(), Box<dyn Error>> {
+    write_u64(wtr, v.len() as u64)?;
+    wtr.write_all(v.as_bytes())?;
+    Ok(())
+}
+
+fn read_u32(rdr: &mut impl Read) -> Result<u32, Box<dyn Error>> {
+    let mut buf = [0; 4];
+    rdr.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64(rdr: &mut impl Read) -> Result<u64, Box<dyn Error>> {
+    let mut buf = [0; 8];
+    rdr.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn read_f32(rdr: &mut impl Read) -> Result<f32, Box<dyn Error>> {
+    let mut buf = [0; 4];
+    rdr.read_exact(&mut buf)?;
+    Ok(f32::from_le_bytes(buf))
+}
+
+fn read_str(rdr: &mut impl Read) -> Result<String, Box<dyn Error>> {
+    let len = read_u64(rdr)?;
+    let mut buf = Vec::new();
+    rdr.take(len).read_to_end(&mut buf)?;
+    if buf.len() as u64 != len {
+        return Err(io::Error::from(ErrorKind::UnexpectedEof).into());
+    }
+    Ok(String::from_utf8(buf)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("disco-{}-{}", std::process::id(), name))
+    }
+
+    fn model() -> Model {
+        Model::from_parts(
+            true,
+            2,
+            3.5,
+            0.1,
+            40.0,
+            vec!["u1".to_string(), "u2".to_string()],
+            vec!["i1".to_string(), "i2".to_string(), "i3".to_string()],
+            vec![0.1, 0.2, 0.3, 0.4],
+            vec![0.5, 0.6, 0.7, 0.8, 0.9, 1.0],
+            vec![vec![2, 0], vec![1]],
+        )
+    }
+
+    #[test]
+    fn test_save_load() {
+        let path = temp_path("save-load.bin");
+        let model = model();
+        model.save(&path, true).unwrap();
+        let loaded = Model::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(loaded.explicit());
+        assert_eq!(model.factors(), loaded.factors());
+        assert_eq!(model.global_mean, loaded.global_mean);
+        assert_eq!(model.regularization, loaded.regularization);
+        assert_eq!(model.alpha, loaded.alpha);
+        assert_eq!(model.user_ids(), loaded.user_ids());
+        assert_eq!(model.item_ids(), loaded.item_ids());
+        assert_eq!(model.user_factors(), loaded.user_factors());
+        assert_eq!(model.item_factors(), loaded.item_factors());
+        assert_eq!(vec![vec![0, 2], vec![1]], loaded.rated);
+    }
+
+    #[test]
+    fn test_load_truncated() {
+        let path = temp_path("truncated.bin");
+        model().save(&path, true).unwrap();
+        let contents = fs::read(&path).unwrap();
+        fs::write(&path, &contents[..contents.len() - 3]).unwrap();
+        let error = Model::load(&path).err().unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            format!("Invalid model: {}", path.display()),
+            error.to_string()
+        );
+    }
+
+    #[test]
+    fn test_load_invalid_header() {
+        let path = temp_path("invalid-header.bin");
+        let mut contents = MAGIC.to_vec();
+        contents.extend_from_slice(&VERSION.to_le_bytes());
+        contents.push(1);
+        contents.extend_from_slice(&u64::MAX.to_le_bytes());
+        contents.extend_from_slice(&0.0f32.to_le_bytes());
+        contents.extend_from_slice(&u64::MAX.to_le_bytes());
+        fs::write(&path, &contents).unwrap();
+        let error = Model::load(&path).err().unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            format!("Invalid model: {}", path.display()),
+            error.to_string()
+        );
+    }
+
+    #[test]
+    fn test_load_zero_factors() {
+        let path = temp_path("zero-factors.bin");
+        let mut contents = MAGIC.to_vec();
+        contents.extend_from_slice(&VERSION.to_le_bytes());
+        contents.push(1);
+        contents.extend_from_slice(&0u64.to_le_bytes());
+        fs::write(&path, &contents).unwrap();
+        let error = Model::load(&path).err().unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            format!("Invalid model: {}", path.display()),
+            error.to_string()
+        );
+    }
+}
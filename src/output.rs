@@ -0,0 +1,420 @@
+use crate::compression::{CompressedWriter, format_extension};
+use crate::helpers::*;
+use std::error::Error;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+#[cfg(feature = "parquet")]
+use parquet::{
+    basic::{Compression, LogicalType, Repetition, Type as PhysicalType},
+    data_type::{ByteArray, ByteArrayType, FloatType},
+    file::{properties::WriterProperties, writer::SerializedFileWriter},
+    schema::types::Type,
+};
+#[cfg(feature = "parquet")]
+use std::sync::Arc;
+
+#[derive(Clone, Copy, Debug)]
+pub enum Column {
+    String,
+    // empty values are null
+    Float,
+}
+
+// writes CSV or Parquet depending on the extension
+pub enum RecordWriter {
+    Csv(csv::Writer<CompressedWriter>),
+    #[cfg(feature = "parquet")]
+    Parquet(ParquetWriter),
+}
+
+impl RecordWriter {
+    // Parquet support is checked upfront to fail fast
+    pub fn check_format(path: &Path) -> Result<(), Box<dyn Error>> {
+        if is_parquet(path) && !cfg!(feature = "parquet") {
+            return Err(parquet_not_enabled());
+        }
+        Ok(())
+    }
+
+    pub fn create(
+        path: &Path,
+        columns: &[(&str, Column)],
+        overwrite: bool,
+    ) -> Result<RecordWriter, Box<dyn Error>> {
+        if is_parquet(path) {
+            return create_parquet(path, columns, overwrite);
+        }
+
+        let mut wtr = create_csv(path, overwrite)?;
+        wtr.write_record(columns.iter().map(|c| c.0))?;
+        Ok(RecordWriter::Csv(wtr))
+    }
+
+    pub fn write_record<I, T>(&mut self, record: I) -> Result<(), Box<dyn Error>>
+    where
+        I: IntoIterator<Item = T>,
+        T: AsRef<str>,
+    {
+        match self {
+            RecordWriter::Csv(wtr) => {
+                for value in record {
+                    wtr.write_field(value.as_ref())?;
+                }
+                // terminate the record
+                wtr.write_record(None::<&[u8]>)?;
+            }
+            #[cfg(feature = "parquet")]
+            RecordWriter::Parquet(wtr) => wtr.write_record(record)?,
+        }
+        Ok(())
+    }
+
+    pub fn finish(self) -> Result<(), Box<dyn Error>> {
+        match self {
+            RecordWriter::Csv(wtr) => finish_csv(wtr)?,
+            #[cfg(feature = "parquet")]
+            RecordWriter::Parquet(wtr) => wtr.finish()?,
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum RecsFormat {
+    Csv,
+    Jsonl,
+    Json,
+    Wide,
+}
+
+impl RecsFormat {
+    pub fn variants() -> [&'static str; 4] {
+        ["csv", "jsonl", "json", "wide"]
+    }
+
+    // infer from extension
+    fn from_path(path: &Path) -> RecsFormat {
+        match format_extension(path) {
+            Some("jsonl") => RecsFormat::Jsonl,
+            Some("json") => RecsFormat::Json,
+            _ => RecsFormat::Csv,
+        }
+    }
+}
+
+impl FromStr for RecsFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<RecsFormat, Self::Err> {
+        match s {
+            "csv" => Ok(RecsFormat::Csv),
+            "jsonl" => Ok(RecsFormat::Jsonl),
+            "json" => Ok(RecsFormat::Json),
+            "wide" => Ok(RecsFormat::Wide),
+            // not shown since possible_values used
+            _ => Err(format!("Invalid format: {s}")),
+        }
+    }
+}
+
+impl std::fmt::Display for RecsFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RecsFormat::Csv => write!(f, "csv"),
+            RecsFormat::Jsonl => write!(f, "jsonl"),
+            RecsFormat::Json => write!(f, "json"),
+            RecsFormat::Wide => write!(f, "wide"),
+        }
+    }
+}
+
+// writes all recs for an id at once
+pub enum RecsWriter {
+    Long(RecordWriter),
+    // one column per rec
+    Wide(RecordWriter, usize),
+    Json {
+        wtr: BufWriter<CompressedWriter>,
+        // id and rec keys
+        keys: [&'static str; 2],
+        // JSON Lines or an array
+        lines: bool,
+        records: usize,
+    },
+}
+
+impl RecsWriter {
+    // JSON can't be written to a Parquet path (checked upfront to fail fast)
+    pub fn check_format(path: &Path, format: Option<&RecsFormat>) -> Result<(), Box<dyn Error>> {
+        if let Some(format @ (RecsFormat::Json | RecsFormat::Jsonl)) = format
+            && is_parquet(path)
+        {
+            return Err(format!("Cannot write {format} to Parquet: {}", path.display()).into());
+        }
+        RecordWriter::check_format(path)
+    }
+
+    // columns are the id, the recommended id, and the recommended id key for JSON
+    pub fn create(
+        path: &Path,
+        format: Option<&RecsFormat>,
+        columns: [&'static str; 3],
+        count: usize,
+        overwrite: bool,
+    ) -> Result<RecsWriter, Box<dyn Error>> {
+        RecsWriter::check_format(path, format)?;
+
+        let [id_column, rec_column, rec_key] = columns;
+        let format = format
+            .cloned()
+            .unwrap_or_else(|| RecsFormat::from_path(path));
+        let wtr = match format {
+            RecsFormat::Csv => RecsWriter::Long(RecordWriter::create(
+                path,
+                &[
+                    (id_column, Column::String),
+                    (rec_column, Column::String),
+                    ("score", Column::Float),
+                ],
+                overwrite,
+            )?),
+            RecsFormat::Wide => {
+                let names: Vec<String> = (1..=count).map(|n| format!("{rec_column}_{n}")).collect();
+                let mut header = vec![(id_column, Column::String)];
+                header.extend(names.iter().map(|n| (n.as_str(), Column::String)));
+                RecsWriter::Wide(RecordWriter::create(path, &header, overwrite)?, count)
+            }
+            RecsFormat::Jsonl | RecsFormat::Json => {
+                let lines = matches!(format, RecsFormat::Jsonl);
+                let mut wtr = BufWriter::new(create_output(path, overwrite)?);
+                if !lines {
+                    write!(wtr, "[")?;
+                }
+                RecsWriter::Json {
+                    wtr,
+                    keys: [id_column, rec_key],
+                    lines,
+                    records: 0,
+                }
+            }
+        };
+        Ok(wtr)
+    }
+
+    pub fn write<T: AsRef<str>>(
+        &mut self,
+        id: &str,
+        recs: &[(T, f32)],
+    ) -> Result<(), Box<dyn Error>> {
+        match self {
+            RecsWriter::Long(wtr) => {
+                for (rec, score) in recs {
+                    wtr.write_record([id, rec.as_ref(), &score.to_string()])?;
+                }
+            }
+            RecsWriter::Wide(wtr, count) => {
+                // pad when there are fewer recs
+                let mut record = vec![id];
+                record.extend(recs.iter().map(|v| v.0.as_ref()));
+                record.resize(*count + 1, "");
+                wtr.write_record(record)?;
+            }
+            RecsWriter::Json {
+                wtr,
+                keys,
+                lines,
+                records,
+            } => {
+                let recs: Vec<String> = recs
+                    .iter()
+                    .map(|(rec, score)| {
+                        format!(
+                            "{{\"{}\": {}, \"score\": {}}}",
+                            keys[1],
+                            json_string(rec.as_ref()),
+                            json_number(*score)
+                        )
+                    })
+                    .collect();
+                let record = format!(
+                    "{{\"{}\": {}, \"recs\": [{}]}}",
+                    keys[0],
+                    json_string(id),
+                    recs.join(", ")
+                );
+                if *lines {
+                    writeln!(wtr, "{record}")?;
+                } else {
+                    let separator = if *records == 0 { "" } else { "," };
+                    write!(wtr, "{separator}\n  {record}")?;
+                }
+                *records += 1;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn finish(self) -> Result<(), Box<dyn Error>> {
+        match self {
+            RecsWriter::Long(wtr) | RecsWriter::Wide(wtr, _) => wtr.finish()?,
+            RecsWriter::Json {
+                mut wtr,
+                lines,
+                records,
+                ..
+            } => {
+                if !lines {
+                    if records > 0 {
+                        writeln!(wtr)?;
+                    }
+                    writeln!(wtr, "]")?;
+                }
+                finish_output(wtr)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn is_parquet(path: &Path) -> bool {
+    path.extension().is_some_and(|e| e == "parquet")
+}
+
+pub fn parquet_not_enabled() -> Box<dyn Error> {
+    "Parquet support not enabled. Install with: cargo install disco-cli --features parquet".into()
+}
+
+#[cfg(not(feature = "parquet"))]
+fn create_parquet(
+    _path: &Path,
+    _columns: &[(&str, Column)],
+    _overwrite: bool,
+) -> Result<RecordWriter, Box<dyn Error>> {
+    Err(parquet_not_enabled())
+}
+
+#[cfg(feature = "parquet")]
+fn create_parquet(
+    path: &Path,
+    columns: &[(&str, Column)],
+    overwrite: bool,
+) -> Result<RecordWriter, Box<dyn Error>> {
+    let mut fields = Vec::with_capacity(columns.len());
+    for (name, column) in columns {
+        let field = match column {
+            Column::String => Type::primitive_type_builder(name, PhysicalType::BYTE_ARRAY)
+                .with_logical_type(Some(LogicalType::String))
+                .with_repetition(Repetition::REQUIRED),
+            Column::Float => Type::primitive_type_builder(name, PhysicalType::FLOAT)
+                .with_repetition(Repetition::OPTIONAL),
+        };
+        fields.push(Arc::new(field.build()?));
+    }
+    let schema = Type::group_type_builder("schema")
+        .with_fields(fields)
+        .build()?;
+    let properties = WriterProperties::builder()
+        .set_compression(Compression::SNAPPY)
+        .build();
+
+    let file = create_output(path, overwrite)?;
+    let writer = SerializedFileWriter::new(file, Arc::new(schema), Arc::new(properties))?;
+    let buffers = columns
+        .iter()
+        .map(|(_, column)| match column {
+            Column::String => Buffer::String(Vec::new()),
+            Column::Float => Buffer::Float(Vec::new(), Vec::new()),
+        })
+        .collect();
+
+    Ok(RecordWriter::Parquet(ParquetWriter {
+        writer,
+        buffers,
+        rows: 0,
+    }))
+}
+
+#[cfg(feature = "parquet")]
+const ROW_GROUP_SIZE: usize = 500_000;
+
+#[cfg(feature = "parquet")]
+enum Buffer {
+    String(Vec<ByteArray>),
+    // values and definition levels
+    Float(Vec<f32>, Vec<i16>),
+}
+
+// buffers columns for each row group
+#[cfg(feature = "parquet")]
+pub struct ParquetWriter {
+    writer: SerializedFileWriter<CompressedWriter>,
+    buffers: Vec<Buffer>,
+    rows: usize,
+}
+
+#[cfg(feature = "parquet")]
+impl ParquetWriter {
+    fn write_record<I, T>(&mut self, record: I) -> Result<(), Box<dyn Error>>
+    where
+        I: IntoIterator<Item = T>,
+        T: AsRef<str>,
+    {
+        for (buffer, value) in self.buffers.iter_mut().zip(record) {
+            let value = value.as_ref();
+            match buffer {
+                Buffer::String(values) => values.push(ByteArray::from(value)),
+                Buffer::Float(values, levels) => {
+                    if value.is_empty() {
+                        levels.push(0);
+                    } else {
+                        values.push(value.parse::<f32>()?);
+                        levels.push(1);
+                    }
+                }
+            }
+        }
+
+        self.rows += 1;
+        if self.rows == ROW_GROUP_SIZE {
+            self.flush_row_group()?;
+        }
+        Ok(())
+    }
+
+    fn flush_row_group(&mut self) -> Result<(), Box<dyn Error>> {
+        let mut row_group = self.writer.next_row_group()?;
+        for buffer in &mut self.buffers {
+            // safe to unwrap since there is a buffer for each column
+            let mut column = row_group.next_column()?.unwrap();
+            match buffer {
+                Buffer::String(values) => {
+                    column
+                        .typed::<ByteArrayType>()
+                        .write_batch(values, None, None)?;
+                    values.clear();
+                }
+                Buffer::Float(values, levels) => {
+                    column
+                        .typed::<FloatType>()
+                        .write_batch(values, Some(levels), None)?;
+                    values.clear();
+                    levels.clear();
+                }
+            }
+            column.close()?;
+        }
+        row_group.close()?;
+        self.rows = 0;
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<(), Box<dyn Error>> {
+        if self.rows > 0 {
+            self.flush_row_group()?;
+        }
+        self.writer.into_inner()?.finish()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,108 @@
+use crate::helpers::*;
+use crate::output::{Column, RecordWriter};
+use crate::train::{ModelOptions, load_model};
+use std::error::Error;
+use std::path::Path;
+use std::str::FromStr;
+
+#[derive(Clone, Debug)]
+pub enum Fallback {
+    GlobalMean,
+    Empty,
+    Error,
+}
+
+impl Fallback {
+    pub fn variants() -> [&'static str; 3] {
+        ["global-mean", "empty", "error"]
+    }
+}
+
+impl FromStr for Fallback {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Fallback, Self::Err> {
+        match s {
+            "global-mean" => Ok(Fallback::GlobalMean),
+            "empty" => Ok(Fallback::Empty),
+            "error" => Ok(Fallback::Error),
+            // not shown since possible_values used
+            _ => Err(format!("Invalid fallback: {s}")),
+        }
+    }
+}
+
+impl std::fmt::Display for Fallback {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Fallback::GlobalMean => write!(f, "global-mean"),
+            Fallback::Empty => write!(f, "empty"),
+            Fallback::Error => write!(f, "error"),
+        }
+    }
+}
+
+pub fn predict(
+    model_options: &ModelOptions,
+    pairs: &Path,
+    output: &Path,
+    fallback: &Fallback,
+    overwrite: bool,
+) -> Result<(), Box<dyn Error>> {
+    if !overwrite {
+        check_exists(output)?;
+    }
+    RecordWriter::check_format(output)?;
+
+    // open before training to fail fast
+    let file = open_input(pairs)?;
+    let mut rdr = csv::Reader::from_reader(file);
+
+    let headers = rdr.headers()?;
+    let user_index = headers
+        .iter()
+        .position(|r| r == "user_id")
+        .ok_or("Missing user_id column")?;
+    let item_index = headers
+        .iter()
+        .position(|r| r == "item_id")
+        .ok_or("Missing item_id column")?;
+
+    let model = load_model(model_options, overwrite)?;
+
+    let mut wtr = RecordWriter::create(
+        output,
+        &[
+            ("user_id", Column::String),
+            ("item_id", Column::String),
+            ("score", Column::Float),
+        ],
+        overwrite,
+    )?;
+
+    for (i, result) in rdr.records().enumerate() {
+        let record = result?;
+
+        // safe to unwrap since csv::Reader checks for same number of columns as header
+        let user_id = record.get(user_index).unwrap();
+        let item_id = record.get(item_index).unwrap();
+
+        let known = model.has_user(user_id) && model.has_item(item_id);
+        let score = match fallback {
+            Fallback::Empty if !known => String::new(),
+            Fallback::Error if !model.has_user(user_id) => {
+                return Err(format!("Unknown user: {} (line: {})", user_id, i + 2).into());
+            }
+            Fallback::Error if !model.has_item(item_id) => {
+                return Err(format!("Unknown item: {} (line: {})", item_id, i + 2).into());
+            }
+            _ => model.predict(user_id, item_id).to_string(),
+        };
+
+        wtr.write_record([user_id, item_id, &score])?;
+    }
+
+    wtr.finish()?;
+
+    Ok(())
+}
@@ -0,0 +1,68 @@
+use std::time::{Duration, SystemTime};
+
+// same generator as discorec
+pub struct Prng {
+    s: [u64; 4],
+}
+
+pub fn random_seed() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or(Duration::ZERO)
+        .subsec_nanos()
+        .into()
+}
+
+impl Prng {
+    pub fn new(seed: Option<u64>) -> Self {
+        let seed = seed.unwrap_or_else(random_seed);
+
+        let mut x = seed;
+        let mut s = [0; 4];
+        for v in &mut s {
+            *v = splitmix64(&mut x);
+        }
+        Self { s }
+    }
+
+    // use upper 53 bits as recommended by xoshiro256+ authors
+    pub fn next(&mut self) -> f64 {
+        let bits = xoshiro256plus(&mut self.s) >> 11;
+        bits as f64 / (1_u64 << 53) as f64
+    }
+
+    pub fn shuffle<T>(&mut self, v: &mut [T]) {
+        // Fisher–Yates shuffle
+        for i in (1..v.len()).rev() {
+            let j = (self.next() * (i as f64 + 1.0)) as usize;
+            v.swap(i, j);
+        }
+    }
+}
+
+// https://prng.di.unimi.it/xoshiro256plus.c
+fn xoshiro256plus(s: &mut [u64; 4]) -> u64 {
+    let result = s[0].wrapping_add(s[3]);
+
+    let t = s[1] << 17;
+
+    s[2] ^= s[0];
+    s[3] ^= s[1];
+    s[1] ^= s[2];
+    s[0] ^= s[3];
+
+    s[2] ^= t;
+
+    s[3] = s[3].rotate_left(45);
+
+    result
+}
+
+// https://prng.di.unimi.it/splitmix64.c
+fn splitmix64(x: &mut u64) -> u64 {
+    *x = x.wrapping_add(0x9e3779b97f4a7c15);
+    let mut z = *x;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
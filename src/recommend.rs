@@ -0,0 +1,108 @@
+use crate::helpers::*;
+use crate::input::read_interactions;
+use crate::output::{Column, RecordWriter};
+use crate::recs::{FilterOptions, report_unknown};
+use crate::train::{ModelOptions, load_model};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::Path;
+
+pub fn recommend_for(
+    model_options: &ModelOptions,
+    output: &Path,
+    items: &[String],
+    interactions: Option<&Path>,
+    count: usize,
+    filter_options: &FilterOptions,
+    overwrite: bool,
+) -> Result<(), Box<dyn Error>> {
+    if !overwrite {
+        check_exists(output)?;
+    }
+    RecordWriter::check_format(output)?;
+
+    // read before training to fail fast
+    let batch = interactions
+        .map(|path| read_interactions(path, model_options.input_options(), false))
+        .transpose()?;
+
+    let recommender = load_model(model_options, overwrite)?;
+
+    // there are no ratings with a list of items
+    if recommender.explicit() && batch.is_none() {
+        return Err("Explicit feedback requires ratings. Use --interactions.".into());
+    }
+
+    // item indices and values for each new user
+    let mut users: BTreeMap<String, Vec<(usize, f32)>> = BTreeMap::new();
+    let mut unknown = Vec::new();
+    let data = match &batch {
+        Some(batch) => batch
+            .data
+            .iter()
+            .map(|&(u, i, v)| {
+                (
+                    batch.user_ids[u as usize].as_str(),
+                    batch.item_ids[i as usize].as_str(),
+                    v,
+                )
+            })
+            .collect(),
+        None => items
+            .iter()
+            .map(|i| ("", i.as_str(), 1.0))
+            .collect::<Vec<_>>(),
+    };
+    for (user_id, item_id, value) in data {
+        let interactions = users.entry(user_id.to_string()).or_default();
+        match recommender.item_index(item_id) {
+            Some(i) => interactions.push((i, value)),
+            None => unknown.push(item_id.to_string()),
+        }
+    }
+    unknown.sort_unstable();
+    unknown.dedup();
+    report_unknown("Items", &unknown);
+
+    let mask = filter_options.item_mask(&recommender)?;
+
+    let mut columns = vec![
+        ("recommended_item_id", Column::String),
+        ("score", Column::Float),
+    ];
+    if batch.is_some() {
+        columns.insert(0, ("user_id", Column::String));
+    }
+    let mut wtr = RecordWriter::create(output, &columns, overwrite)?;
+
+    let bar = progress_bar(
+        users.len() as u64,
+        "Saving recs",
+        "{msg} {wide_bar} {pos}/{len}",
+    );
+
+    for (user, mut interactions) in users {
+        // no signal without known items
+        if !interactions.is_empty() {
+            let factors = recommender.fold_in(&interactions);
+
+            interactions.sort_unstable_by_key(|v| v.0);
+            let recs = recommender.recs_for_factors(&factors, count, |i| {
+                mask[i] && interactions.binary_search_by_key(&i, |v| v.0).is_err()
+            });
+            for (recommended_item, score) in recs {
+                if batch.is_some() {
+                    wtr.write_record([&user, recommended_item, &score.to_string()])?;
+                } else {
+                    wtr.write_record([recommended_item, &score.to_string()])?;
+                }
+            }
+        }
+        bar.inc(1);
+    }
+
+    wtr.finish()?;
+    bar.finish();
+
+    Ok(())
+}
@@ -1,108 +1,275 @@
+use crate::ann::AnnOptions;
 use crate::helpers::*;
-use discorec::{Recommender, RecommenderBuilder};
+use crate::model::Model;
+use crate::output::{RecsFormat, RecsWriter};
+use crate::train::{ModelOptions, load_model};
+use clap::Args;
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
-use std::fs::File;
-use std::io::ErrorKind;
-use std::path::Path;
-
-fn fit_recommender(
-    input: &Path,
-    factors: u32,
-    iterations: u32,
-) -> Result<Recommender<String, String>, Box<dyn Error>> {
-    let file = File::open(input).map_err(|e| -> Box<dyn Error> {
-        if e.kind() == ErrorKind::NotFound {
-            format!("File not found: {}", input.display()).into()
-        } else {
-            e.into()
-        }
-    })?;
-    let mut rdr = csv::Reader::from_reader(file);
-
-    let headers = rdr.headers()?;
-    let explicit = !headers.iter().any(|r| r == "value");
-    let value_header = if explicit { "rating" } else { "value" };
-
-    let user_index = headers
-        .iter()
-        .position(|r| r == "user_id")
-        .ok_or("Missing user_id column")?;
-    let item_index = headers
-        .iter()
-        .position(|r| r == "item_id")
-        .ok_or("Missing item_id column")?;
-    let value_index = headers
-        .iter()
-        .position(|r| r == value_header)
-        .ok_or("Missing rating/value column")?;
-
-    let mut dataset = Vec::new();
-    for (i, result) in rdr.records().enumerate() {
-        let record = result?;
-
-        // safe to unwrap since csv::Reader checks for same number of columns as header
-        dataset.push((
-            record.get(user_index).unwrap().to_string(),
-            record.get(item_index).unwrap().to_string(),
-            // match CSV error: record 1 (line: 2, byte: 23): found record with 2 fields
-            record
-                .get(value_index)
-                .unwrap()
-                .parse::<f32>()
-                .map_err(|e| {
-                    format!(
-                        "Input error: record {} (line: {}, column: {}): {}",
-                        i + 1,
-                        i + 2,
-                        value_header,
-                        e
-                    )
-                })?,
-        ));
+use std::path::{Path, PathBuf};
+use std::thread;
+
+// options for generating output for a subset of users
+#[derive(Debug, Args)]
+pub struct UserSelection {
+    #[arg(long)]
+    users: Option<PathBuf>,
+
+    #[arg(long)]
+    user: Vec<String>,
+}
+
+impl UserSelection {
+    fn read(&self) -> Result<Option<Vec<String>>, Box<dyn Error>> {
+        read_selection(self.users.as_deref(), &self.user, "user_id")
     }
+}
 
-    let bar = progress_bar(
-        iterations as u64,
-        "Training model",
-        "{msg} {wide_bar} {percent}%",
-    );
+// options for generating output for a subset of items
+#[derive(Debug, Args)]
+pub struct ItemSelection {
+    #[arg(long)]
+    items: Option<PathBuf>,
 
-    let cb = |_| {
-        bar.inc(1);
-    };
+    #[arg(long)]
+    item: Vec<String>,
+}
+
+impl ItemSelection {
+    fn read(&self) -> Result<Option<Vec<String>>, Box<dyn Error>> {
+        read_selection(self.items.as_deref(), &self.item, "item_id")
+    }
+}
+
+fn read_selection(
+    path: Option<&Path>,
+    ids: &[String],
+    column: &str,
+) -> Result<Option<Vec<String>>, Box<dyn Error>> {
+    if path.is_none() && ids.is_empty() {
+        return Ok(None);
+    }
 
-    let mut builder = RecommenderBuilder::new();
-    builder.factors(factors).iterations(iterations).callback(cb);
+    let mut selected = ids.to_vec();
+    if let Some(path) = path {
+        selected.extend(read_columns(path, [column])?.into_iter().map(|[id]| id));
+    }
+    Ok(Some(selected))
+}
 
-    let recommender = if explicit {
-        builder.fit_explicit(&dataset)
-    } else {
-        builder.fit_implicit(&dataset)
+// sorted ids to generate output for
+fn select_ids<F>(ids: &[String], selected: Option<Vec<String>>, name: &str, known: F) -> Vec<String>
+where
+    F: Fn(&str) -> bool,
+{
+    let Some(selected) = selected else {
+        let mut ids = ids.to_vec();
+        ids.sort_unstable();
+        return ids;
     };
 
-    bar.finish();
+    let (mut ids, mut unknown): (Vec<_>, Vec<_>) = selected.into_iter().partition(|id| known(id));
+    ids.sort_unstable();
+    ids.dedup();
+    unknown.sort_unstable();
+    unknown.dedup();
+
+    report_unknown(name, &unknown);
+
+    ids
+}
+
+// ids are sorted and deduped
+pub fn report_unknown(name: &str, unknown: &[String]) {
+    if unknown.is_empty() {
+        return;
+    }
+
+    let mut message = format!(
+        "{} not in training data: {} ({}",
+        name,
+        unknown.len(),
+        unknown[..unknown.len().min(10)].join(", ")
+    );
+    if unknown.len() > 10 {
+        message.push_str(", ...");
+    }
+    eprintln!("{message})");
+}
+
+// options for filtering recommended items
+#[derive(Debug, Args)]
+pub struct FilterOptions {
+    #[arg(long)]
+    exclude_items: Option<PathBuf>,
+
+    #[arg(long)]
+    allowed_items: Option<PathBuf>,
+}
+
+impl FilterOptions {
+    // whether each item in the model can be recommended
+    pub fn item_mask(&self, model: &Model) -> Result<Vec<bool>, Box<dyn Error>> {
+        let mut mask = vec![self.allowed_items.is_none(); model.item_ids().len()];
+        if let Some(path) = &self.allowed_items {
+            for [item_id] in read_columns(path, ["item_id"])? {
+                if let Some(i) = model.item_index(&item_id) {
+                    mask[i] = true;
+                }
+            }
+        }
+        if let Some(path) = &self.exclude_items {
+            for [item_id] in read_columns(path, ["item_id"])? {
+                if let Some(i) = model.item_index(&item_id) {
+                    mask[i] = false;
+                }
+            }
+        }
+        Ok(mask)
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct UserFilterOptions {
+    #[arg(long, overrides_with = "include_seen")]
+    exclude_seen: bool,
+
+    #[arg(long, overrides_with = "exclude_seen")]
+    include_seen: bool,
 
-    Ok(recommender)
+    #[arg(long)]
+    exclude_user_items: Option<PathBuf>,
+
+    #[command(flatten)]
+    filter_options: FilterOptions,
+}
+
+impl UserFilterOptions {
+    // excluded by default
+    fn exclude_seen(&self) -> bool {
+        self.exclude_seen || !self.include_seen
+    }
+
+    // sorted item indices to exclude for each user
+    fn user_exclusions(
+        &self,
+        model: &Model,
+    ) -> Result<HashMap<String, Vec<usize>>, Box<dyn Error>> {
+        let mut exclusions: HashMap<String, Vec<usize>> = HashMap::new();
+        if let Some(path) = &self.exclude_user_items {
+            for [user_id, item_id] in read_columns(path, ["user_id", "item_id"])? {
+                if let Some(i) = model.item_index(&item_id) {
+                    exclusions.entry(user_id).or_default().push(i);
+                }
+            }
+        }
+        for items in exclusions.values_mut() {
+            items.sort_unstable();
+        }
+        Ok(exclusions)
+    }
+}
+
+// compute in batches across threads and write in the original order
+fn for_each_parallel<'a, T, F, W>(
+    ids: &'a [String],
+    threads: usize,
+    compute: F,
+    mut write: W,
+) -> Result<(), Box<dyn Error>>
+where
+    T: Send,
+    F: Fn(&'a String) -> T + Sync,
+    W: FnMut(&'a String, T) -> Result<(), Box<dyn Error>>,
+{
+    for batch in ids.chunks(1024 * threads) {
+        let results: Vec<T> = if threads == 1 {
+            batch.iter().map(&compute).collect()
+        } else {
+            let chunk_size = batch.len().div_ceil(threads);
+            thread::scope(|s| {
+                let handles: Vec<_> = batch
+                    .chunks(chunk_size)
+                    .map(|chunk| s.spawn(|| chunk.iter().map(&compute).collect::<Vec<_>>()))
+                    .collect();
+                handles
+                    .into_iter()
+                    .flat_map(|h| h.join().unwrap())
+                    .collect()
+            })
+        };
+
+        for (id, result) in batch.iter().zip(results) {
+            write(id, result)?;
+        }
+    }
+    Ok(())
+}
+
+// compare with exact search on an evenly spaced sample
+fn report_recall<'a, F, G>(ids: &'a [String], sample: usize, exact: F, approximate: G)
+where
+    F: Fn(&'a String) -> Vec<(&'a String, f32)>,
+    G: Fn(&'a String) -> Vec<(&'a String, f32)>,
+{
+    if sample == 0 || ids.is_empty() {
+        return;
+    }
+
+    let step = ids.len().div_ceil(sample);
+    let mut found = 0;
+    let mut total = 0;
+    let mut samples = 0;
+    for id in ids.iter().step_by(step) {
+        let approximate: HashSet<&String> = approximate(id).into_iter().map(|v| v.0).collect();
+        let exact = exact(id);
+        found += exact.iter().filter(|v| approximate.contains(v.0)).count();
+        total += exact.len();
+        samples += 1;
+    }
+
+    if total > 0 {
+        eprintln!(
+            "ANN recall: {:.4} ({} samples)",
+            found as f64 / total as f64,
+            samples
+        );
+    }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn user_recs(
-    input: &Path,
+    model_options: &ModelOptions,
     output: &Path,
     count: usize,
-    factors: u32,
-    iterations: u32,
+    selection: &UserSelection,
+    filter_options: &UserFilterOptions,
+    format: Option<&RecsFormat>,
+    threads: usize,
     overwrite: bool,
 ) -> Result<(), Box<dyn Error>> {
     if !overwrite {
         check_exists(output)?;
     }
-
-    let recommender = fit_recommender(input, factors, iterations)?;
-    let mut user_ids = recommender.user_ids().to_vec();
-    user_ids.sort_unstable();
-
-    let mut wtr = create_csv(output, overwrite)?;
-    wtr.write_record(["user_id", "recommended_item_id", "score"])?;
+    RecsWriter::check_format(output, format)?;
+
+    let selected = selection.read()?;
+    let recommender = load_model(model_options, overwrite)?;
+    let user_ids = select_ids(recommender.user_ids(), selected, "Users", |id| {
+        recommender.has_user(id)
+    });
+
+    let exclude_seen = filter_options.exclude_seen();
+    let mask = filter_options.filter_options.item_mask(&recommender)?;
+    let exclusions = filter_options.user_exclusions(&recommender)?;
+
+    let mut wtr = RecsWriter::create(
+        output,
+        format,
+        ["user_id", "recommended_item_id", "item_id"],
+        count,
+        overwrite,
+    )?;
 
     let bar = progress_bar(
         user_ids.len() as u64,
@@ -110,37 +277,59 @@ pub fn user_recs(
         "{msg} {wide_bar} {pos}/{len}",
     );
 
-    for user in &user_ids {
-        for (recommended_item, score) in recommender.user_recs(user, count) {
-            wtr.write_record([user, recommended_item, &score.to_string()])?;
-        }
+    let recs = |user: &String| {
+        let excluded = exclusions
+            .get(user)
+            .map(|v| v.as_slice())
+            .unwrap_or_default();
+        recommender.user_recs_with(user, count, exclude_seen, |i| {
+            mask[i] && excluded.binary_search(&i).is_err()
+        })
+    };
+    for_each_parallel(&user_ids, threads, recs, |user, recs| {
+        wtr.write(user, &recs)?;
         bar.inc(1);
-    }
+        Ok(())
+    })?;
 
-    wtr.flush()?;
+    wtr.finish()?;
     bar.finish();
 
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn item_recs(
-    input: &Path,
+    model_options: &ModelOptions,
     output: &Path,
     count: usize,
-    factors: u32,
-    iterations: u32,
+    selection: &ItemSelection,
+    filter_options: &FilterOptions,
+    ann_options: &AnnOptions,
+    format: Option<&RecsFormat>,
+    threads: usize,
     overwrite: bool,
 ) -> Result<(), Box<dyn Error>> {
     if !overwrite {
         check_exists(output)?;
     }
+    RecsWriter::check_format(output, format)?;
+
+    let selected = selection.read()?;
+    let recommender = load_model(model_options, overwrite)?;
+    let item_ids = select_ids(recommender.item_ids(), selected, "Items", |id| {
+        recommender.has_item(id)
+    });
 
-    let recommender = fit_recommender(input, factors, iterations)?;
-    let mut item_ids = recommender.item_ids().to_vec();
-    item_ids.sort_unstable();
+    let mask = filter_options.item_mask(&recommender)?;
 
-    let mut wtr = create_csv(output, overwrite)?;
-    wtr.write_record(["item_id", "recommended_item_id", "score"])?;
+    let mut wtr = RecsWriter::create(
+        output,
+        format,
+        ["item_id", "recommended_item_id", "item_id"],
+        count,
+        overwrite,
+    )?;
 
     let bar = progress_bar(
         item_ids.len() as u64,
@@ -148,37 +337,65 @@ pub fn item_recs(
         "{msg} {wide_bar} {pos}/{len}",
     );
 
-    for item in &item_ids {
-        for (recommended_item, score) in recommender.item_recs(item, count) {
-            wtr.write_record([item, recommended_item, &score.to_string()])?;
+    let exact = |item: &String| recommender.item_recs_with(item, count, |i| mask[i]);
+    let index = ann_options.index(recommender.item_factors(), recommender.factors());
+    let recs = |item: &String| match &index {
+        Some(index) => {
+            // safe to unwrap since ids are from the model
+            let i = recommender.item_index(item).unwrap();
+            index
+                .search(i, count, |j| mask[j])
+                .into_iter()
+                .map(|(j, score)| (&recommender.item_ids()[j], score))
+                .collect()
         }
-        bar.inc(1);
+        None => exact(item),
+    };
+    if index.is_some() {
+        report_recall(&item_ids, ann_options.sample(), exact, recs);
     }
 
-    wtr.flush()?;
+    for_each_parallel(&item_ids, threads, recs, |item, recs| {
+        wtr.write(item, &recs)?;
+        bar.inc(1);
+        Ok(())
+    })?;
+
+    wtr.finish()?;
     bar.finish();
 
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn similar_users(
-    input: &Path,
+    model_options: &ModelOptions,
     output: &Path,
     count: usize,
-    factors: u32,
-    iterations: u32,
+    selection: &UserSelection,
+    ann_options: &AnnOptions,
+    format: Option<&RecsFormat>,
+    threads: usize,
     overwrite: bool,
 ) -> Result<(), Box<dyn Error>> {
     if !overwrite {
         check_exists(output)?;
     }
-
-    let recommender = fit_recommender(input, factors, iterations)?;
-    let mut user_ids = recommender.user_ids().to_vec();
-    user_ids.sort_unstable();
-
-    let mut wtr = create_csv(output, overwrite)?;
-    wtr.write_record(["user_id", "similar_user_id", "score"])?;
+    RecsWriter::check_format(output, format)?;
+
+    let selected = selection.read()?;
+    let recommender = load_model(model_options, overwrite)?;
+    let user_ids = select_ids(recommender.user_ids(), selected, "Users", |id| {
+        recommender.has_user(id)
+    });
+
+    let mut wtr = RecsWriter::create(
+        output,
+        format,
+        ["user_id", "similar_user_id", "user_id"],
+        count,
+        overwrite,
+    )?;
 
     let bar = progress_bar(
         user_ids.len() as u64,
@@ -186,14 +403,31 @@ pub fn similar_users(
         "{msg} {wide_bar} {pos}/{len}",
     );
 
-    for user in &user_ids {
-        for (similar_user, score) in recommender.similar_users(user, count) {
-            wtr.write_record([user, similar_user, &score.to_string()])?;
+    let exact = |user: &String| recommender.similar_users(user, count);
+    let index = ann_options.index(recommender.user_factors(), recommender.factors());
+    let similar = |user: &String| match &index {
+        Some(index) => {
+            // safe to unwrap since ids are from the model
+            let u = recommender.user_index(user).unwrap();
+            index
+                .search(u, count, |_| true)
+                .into_iter()
+                .map(|(v, score)| (&recommender.user_ids()[v], score))
+                .collect()
         }
-        bar.inc(1);
+        None => exact(user),
+    };
+    if index.is_some() {
+        report_recall(&user_ids, ann_options.sample(), exact, similar);
     }
 
-    wtr.flush()?;
+    for_each_parallel(&user_ids, threads, similar, |user, similar| {
+        wtr.write(user, &similar)?;
+        bar.inc(1);
+        Ok(())
+    })?;
+
+    wtr.finish()?;
     bar.finish();
 
     Ok(())
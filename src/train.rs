@@ -0,0 +1,541 @@
+use crate::helpers::*;
+use crate::input::*;
+use crate::model::Model;
+use crate::prng::{Prng, random_seed};
+use clap::builder::{PossibleValuesParser, TypedValueParser};
+use clap::{ArgGroup, Args};
+use discorec::{FitInfo, Recommender, RecommenderBuilder};
+use std::borrow::Borrow;
+use std::cell::RefCell;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+#[derive(Clone, Debug)]
+pub enum Algorithm {
+    Mf,
+    Popularity,
+    Random,
+}
+
+impl Algorithm {
+    pub fn variants() -> [&'static str; 3] {
+        ["mf", "popularity", "random"]
+    }
+}
+
+impl FromStr for Algorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Algorithm, Self::Err> {
+        match s {
+            "mf" => Ok(Algorithm::Mf),
+            "popularity" => Ok(Algorithm::Popularity),
+            "random" => Ok(Algorithm::Random),
+            // not shown since possible_values used
+            _ => Err(format!("Invalid algorithm: {s}")),
+        }
+    }
+}
+
+impl std::fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Algorithm::Mf => write!(f, "mf"),
+            Algorithm::Popularity => write!(f, "popularity"),
+            Algorithm::Random => write!(f, "random"),
+        }
+    }
+}
+
+// options for commands that train a model or load a saved one
+#[derive(Debug, Args)]
+pub struct ModelOptions {
+    #[arg(value_parser, required_unless_present = "model")]
+    input: Option<PathBuf>,
+
+    // input options are allowed with recommend-for --interactions
+    #[arg(long, conflicts_with_all = ["input", "TrainOptions"])]
+    model: Option<PathBuf>,
+
+    #[command(flatten)]
+    input_options: InputOptions,
+
+    #[command(flatten)]
+    train_options: TrainOptions,
+}
+
+impl ModelOptions {
+    pub fn input_options(&self) -> &InputOptions {
+        &self.input_options
+    }
+}
+
+#[derive(Clone, Debug, Args)]
+#[command(group = ArgGroup::new("min_interactions").args(["min_user_interactions", "min_item_interactions"]).multiple(true))]
+pub struct TrainOptions {
+    #[arg(long, default_value_t = Algorithm::Mf, value_parser = PossibleValuesParser::new(Algorithm::variants()).map(|s| s.parse::<Algorithm>().unwrap()))]
+    pub algorithm: Algorithm,
+
+    #[arg(long, default_value_t = 8, value_parser = clap::value_parser!(u32).range(1..))]
+    pub factors: u32,
+
+    #[arg(long, default_value_t = 20)]
+    pub iterations: u32,
+
+    #[arg(long)]
+    pub regularization: Option<f32>,
+
+    #[arg(long)]
+    pub learning_rate: Option<f32>,
+
+    #[arg(long)]
+    pub alpha: Option<f32>,
+
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    #[arg(long, value_parser = parse_fraction)]
+    pub validation_size: Option<f64>,
+
+    #[arg(long, requires = "validation_size", value_parser = clap::value_parser!(u32).range(1..))]
+    pub early_stopping_rounds: Option<u32>,
+
+    #[arg(long)]
+    pub loss_log: Option<PathBuf>,
+
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub min_user_interactions: Option<u32>,
+
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub min_item_interactions: Option<u32>,
+
+    #[arg(long, requires = "min_interactions")]
+    pub k_core: bool,
+}
+
+impl TrainOptions {
+    pub fn new(factors: u32, iterations: u32) -> Self {
+        Self {
+            algorithm: Algorithm::Mf,
+            factors,
+            iterations,
+            regularization: None,
+            learning_rate: None,
+            alpha: None,
+            seed: None,
+            validation_size: None,
+            early_stopping_rounds: None,
+            loss_log: None,
+            min_user_interactions: None,
+            min_item_interactions: None,
+            k_core: false,
+        }
+    }
+
+    // same defaults as discorec
+    pub fn regularization_or_default(&self, explicit: bool) -> f32 {
+        self.regularization
+            .unwrap_or(if explicit { 0.1 } else { 0.01 })
+    }
+
+    pub fn alpha_or_default(&self) -> f32 {
+        self.alpha.unwrap_or(40.0)
+    }
+
+    // check upfront since the loss log is written after training
+    pub fn check_exists(&self) -> Result<(), Box<dyn Error>> {
+        if let Some(path) = &self.loss_log {
+            check_exists(path)?;
+        }
+        Ok(())
+    }
+}
+
+// remove users and items with too few interactions before training
+pub fn filter_support(
+    interactions: &mut Interactions,
+    train_options: &TrainOptions,
+) -> Result<(), Box<dyn Error>> {
+    let min_user = train_options.min_user_interactions.unwrap_or(1) as usize;
+    let min_item = train_options.min_item_interactions.unwrap_or(1) as usize;
+    if min_user > 1 || min_item > 1 {
+        interactions.filter_support(min_user, min_item, train_options.k_core)?;
+    }
+    Ok(())
+}
+
+pub fn fit_recommender(
+    input: &Path,
+    input_options: &InputOptions,
+    train_options: &TrainOptions,
+    overwrite: bool,
+) -> Result<Model, Box<dyn Error>> {
+    if !overwrite {
+        train_options.check_exists()?;
+    }
+
+    let mut interactions = read_interactions(input, input_options, false)?;
+    filter_support(&mut interactions, train_options)?;
+    fit(&interactions, &interactions.data, train_options, overwrite)
+}
+
+// dataset can be a subset of the interactions
+pub fn fit(
+    interactions: &Interactions,
+    dataset: &[Row],
+    train_options: &TrainOptions,
+    overwrite: bool,
+) -> Result<Model, Box<dyn Error>> {
+    let explicit = interactions.explicit;
+    if explicit {
+        eprintln!("Using explicit feedback");
+    } else {
+        eprintln!("Using implicit feedback");
+    }
+
+    if !matches!(train_options.algorithm, Algorithm::Mf) {
+        return Ok(fit_baseline(interactions, dataset, train_options));
+    }
+
+    let mut train_options = train_options.clone();
+
+    // hold out a random set for validation loss
+    let mut split = None;
+    if let Some(validation_size) = train_options.validation_size {
+        // discorec only reports loss for explicit feedback
+        if !explicit {
+            return Err("Validation requires explicit feedback".into());
+        }
+
+        // use the same seed if refitting for early stopping
+        let seed = *train_options.seed.get_or_insert_with(random_seed);
+        let mut train_set: Vec<_> = dataset.iter().collect();
+        Prng::new(Some(seed)).shuffle(&mut train_set);
+        let valid_len = (dataset.len() as f64 * validation_size).round() as usize;
+        let valid_set = train_set.split_off(dataset.len() - valid_len);
+        split = Some((train_set, valid_set));
+    }
+
+    let bar = progress_bar(
+        train_options.iterations as u64,
+        "Training model",
+        "{msg} {wide_bar} {percent}%",
+    );
+
+    let history = RefCell::new(Vec::new());
+    let recommender = build_split(
+        dataset,
+        split.as_ref(),
+        explicit,
+        &train_options,
+        |info: FitInfo| {
+            if !info.train_loss.is_nan() {
+                let mut message = format!("Training model (loss: {:.4}", info.train_loss);
+                if !info.valid_loss.is_nan() {
+                    message.push_str(&format!(", validation: {:.4}", info.valid_loss));
+                }
+                message.push(')');
+                bar.set_message(message);
+            }
+            bar.inc(1);
+            history.borrow_mut().push(info);
+        },
+    );
+
+    bar.finish();
+
+    let history = history.into_inner();
+
+    if let Some(path) = &train_options.loss_log {
+        let mut wtr = create_csv(path, overwrite)?;
+        wtr.write_record(["iteration", "train_loss", "valid_loss"])?;
+        for info in &history {
+            wtr.write_record([
+                info.iteration.to_string(),
+                loss_string(info.train_loss),
+                loss_string(info.valid_loss),
+            ])?;
+        }
+        finish_csv(wtr)?;
+    }
+
+    let best = train_options
+        .early_stopping_rounds
+        .and_then(|rounds| best_iteration(&history, rounds));
+    let recommender = match best {
+        Some(best) => {
+            eprintln!("Best iteration {best}, refitting");
+
+            // training is deterministic with the same seed
+            train_options.iterations = best;
+            build_split(dataset, split.as_ref(), explicit, &train_options, |_| {})
+        }
+        None => recommender,
+    };
+
+    // rated items include the validation set to exclude them from recs
+    Ok(Model::new(
+        &recommender,
+        interactions,
+        train_options.factors as usize,
+        train_options.regularization_or_default(explicit),
+        train_options.alpha_or_default(),
+        dataset,
+    ))
+}
+
+pub fn fit_with_callback<C: Fn(FitInfo)>(
+    interactions: &Interactions,
+    dataset: &[Row],
+    train_options: &TrainOptions,
+    callback: C,
+) -> Model {
+    let recommender = build(dataset, &[], interactions.explicit, train_options, callback);
+    Model::new(
+        &recommender,
+        interactions,
+        train_options.factors as usize,
+        train_options.regularization_or_default(interactions.explicit),
+        train_options.alpha_or_default(),
+        dataset,
+    )
+}
+
+// fit on the training set of the split if given, otherwise the full dataset
+fn build_split<C: Fn(FitInfo)>(
+    dataset: &[Row],
+    split: Option<&(Vec<&Row>, Vec<&Row>)>,
+    explicit: bool,
+    train_options: &TrainOptions,
+    callback: C,
+) -> Recommender<u32, u32> {
+    match split {
+        Some((train_set, valid_set)) => {
+            build(train_set, valid_set, explicit, train_options, callback)
+        }
+        None => build(dataset, &[], explicit, train_options, callback),
+    }
+}
+
+fn build<T, C>(
+    train_set: &[T],
+    valid_set: &[T],
+    explicit: bool,
+    train_options: &TrainOptions,
+    callback: C,
+) -> Recommender<u32, u32>
+where
+    T: Borrow<Row>,
+    C: Fn(FitInfo),
+{
+    let mut builder = RecommenderBuilder::new();
+    builder
+        .factors(train_options.factors)
+        .iterations(train_options.iterations)
+        .callback(callback);
+    if let Some(value) = train_options.regularization {
+        builder.regularization(value);
+    }
+    if let Some(value) = train_options.learning_rate {
+        builder.learning_rate(value);
+    }
+    if let Some(value) = train_options.alpha {
+        builder.alpha(value);
+    }
+    if let Some(value) = train_options.seed {
+        builder.seed(value);
+    }
+
+    let train_set = train_set.iter().map(|r| r.borrow());
+    if !valid_set.is_empty() {
+        builder.fit_eval_explicit(train_set, valid_set.iter().map(|r| r.borrow()))
+    } else if explicit {
+        builder.fit_explicit(train_set)
+    } else {
+        builder.fit_implicit(train_set)
+    }
+}
+
+// baselines are stored as factors so they work with all commands
+fn fit_baseline(
+    interactions: &Interactions,
+    dataset: &[Row],
+    train_options: &TrainOptions,
+) -> Model {
+    let explicit = interactions.explicit;
+
+    // indices in the model in order of appearance in the dataset
+    let mut user_map = vec![None; interactions.user_ids.len()];
+    let mut item_map = vec![None; interactions.item_ids.len()];
+    let mut user_ids = Vec::new();
+    let mut item_ids = Vec::new();
+    let mut rated: Vec<Vec<u32>> = Vec::new();
+    let mut popularity: Vec<f32> = Vec::new();
+    let mut counts: Vec<u32> = Vec::new();
+    let mut sum = 0.0;
+
+    for &(user, item, value) in dataset {
+        let u = *user_map[user as usize].get_or_insert_with(|| {
+            user_ids.push(interactions.user_ids[user as usize].clone());
+            rated.push(Vec::new());
+            user_ids.len() - 1
+        });
+        let i = *item_map[item as usize].get_or_insert_with(|| {
+            item_ids.push(interactions.item_ids[item as usize].clone());
+            popularity.push(0.0);
+            counts.push(0);
+            item_ids.len() - 1
+        });
+        rated[u].push(i as u32);
+
+        popularity[i] += value;
+        counts[i] += 1;
+        sum += value;
+    }
+
+    // average rating for explicit feedback so predictions are on the same scale
+    if explicit {
+        for (p, &count) in popularity.iter_mut().zip(&counts) {
+            *p /= count as f32;
+        }
+    }
+
+    let global_mean = if explicit {
+        sum / dataset.len() as f32
+    } else {
+        0.0
+    };
+
+    let (factors, user_factors, item_factors) = match train_options.algorithm {
+        Algorithm::Popularity => (1, vec![1.0; user_ids.len()], popularity),
+        _ => {
+            let factors = train_options.factors as usize;
+            let mut prng = Prng::new(train_options.seed);
+            // centered so each user gets a different order
+            let mut random = |len: usize| {
+                (0..len)
+                    .map(|_| prng.next() as f32 - 0.5)
+                    .collect::<Vec<_>>()
+            };
+            let user_factors = random(user_ids.len() * factors);
+            let mut item_factors = random(item_ids.len() * factors);
+            // unit length so scores don't favor items with larger norms
+            for row in item_factors.chunks_exact_mut(factors) {
+                let norm = row.iter().map(|v| v * v).sum::<f32>().sqrt();
+                if norm > 0.0 {
+                    row.iter_mut().for_each(|v| *v /= norm);
+                }
+            }
+            (factors, user_factors, item_factors)
+        }
+    };
+
+    Model::from_parts(
+        explicit,
+        factors,
+        global_mean,
+        train_options.regularization_or_default(explicit),
+        train_options.alpha_or_default(),
+        user_ids,
+        item_ids,
+        user_factors,
+        item_factors,
+        rated,
+    )
+}
+
+// the iteration with the lowest validation loss
+// if it did not improve for the given number of rounds
+fn best_iteration(history: &[FitInfo], rounds: u32) -> Option<u32> {
+    let mut best: Option<&FitInfo> = None;
+    for info in history {
+        match best {
+            Some(b) if info.valid_loss >= b.valid_loss => {
+                if info.iteration - b.iteration >= rounds {
+                    return Some(b.iteration);
+                }
+            }
+            _ => best = Some(info),
+        }
+    }
+    None
+}
+
+fn loss_string(loss: f32) -> String {
+    if loss.is_nan() {
+        String::new()
+    } else {
+        loss.to_string()
+    }
+}
+
+// load a saved model if given, otherwise train one
+pub fn load_model(options: &ModelOptions, overwrite: bool) -> Result<Model, Box<dyn Error>> {
+    match &options.model {
+        Some(path) => Model::load(path),
+        // safe to unwrap since input is required without model
+        None => fit_recommender(
+            options.input.as_ref().unwrap(),
+            &options.input_options,
+            &options.train_options,
+            overwrite,
+        ),
+    }
+}
+
+pub fn train(
+    input: &Path,
+    output: &Path,
+    input_options: &InputOptions,
+    train_options: &TrainOptions,
+    overwrite: bool,
+) -> Result<(), Box<dyn Error>> {
+    // binary format
+    if is_stdio(output) {
+        return Err("Model output to stdout not supported".into());
+    }
+    if !overwrite {
+        check_exists(output)?;
+    }
+
+    let model = fit_recommender(input, input_options, train_options, overwrite)?;
+    model.save(output, overwrite)?;
+
+    eprintln!("Saved to {}", output.display());
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fit_validation_only_user() {
+        // one dense user and many users with a single rating,
+        // so some users are only in the validation set
+        let mut data = Vec::new();
+        for i in 0..5 {
+            data.push((0, i, 4.0));
+        }
+        for u in 1..=20 {
+            data.push((u, u % 10, 3.0));
+        }
+        let interactions = Interactions {
+            data,
+            user_ids: (0..=20).map(|u| format!("u{u}")).collect(),
+            item_ids: (0..10).map(|i| format!("i{i}")).collect(),
+            explicit: true,
+            timestamps: Vec::new(),
+        };
+
+        let mut train_options = TrainOptions::new(4, 5);
+        train_options.validation_size = Some(0.5);
+        train_options.seed = Some(1);
+        let model = fit(&interactions, &interactions.data, &train_options, false).unwrap();
+
+        assert!(model.user_ids().len() < interactions.user_ids.len());
+        assert!(!model.user_recs("u0", 10).is_empty());
+    }
+}
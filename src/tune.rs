@@ -0,0 +1,334 @@
+use crate::compression::format_extension;
+use crate::evaluate::*;
+use crate::helpers::*;
+use crate::input::*;
+use crate::prng::Prng;
+use crate::train::{TrainOptions, fit_with_callback};
+use clap::Args;
+use clap::builder::{PossibleValuesParser, TypedValueParser};
+use std::error::Error;
+use std::fmt::Display;
+use std::io::Write;
+use std::path::Path;
+use std::str::FromStr;
+
+#[derive(Clone, Debug)]
+pub enum Search {
+    Grid,
+    Random,
+}
+
+impl Search {
+    pub fn variants() -> [&'static str; 2] {
+        ["grid", "random"]
+    }
+}
+
+impl FromStr for Search {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Search, Self::Err> {
+        match s {
+            "grid" => Ok(Search::Grid),
+            "random" => Ok(Search::Random),
+            // not shown since possible_values used
+            _ => Err(format!("Invalid search: {s}")),
+        }
+    }
+}
+
+impl std::fmt::Display for Search {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Search::Grid => write!(f, "grid"),
+            Search::Random => write!(f, "random"),
+        }
+    }
+}
+
+// a comma-separated list of values or a min..max range
+#[derive(Clone, Debug)]
+pub enum Values<T> {
+    List(Vec<T>),
+    Range(T, T),
+}
+
+impl<T: FromStr + PartialOrd> FromStr for Values<T>
+where
+    T::Err: Display,
+{
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Values<T>, Self::Err> {
+        let parse = |v: &str| v.trim().parse::<T>().map_err(|e| format!("{v}: {e}"));
+
+        if let Some((min, max)) = s.split_once("..") {
+            let (min, max) = (parse(min)?, parse(max)?);
+            if min > max {
+                return Err("range min must be less than or equal to max".to_string());
+            }
+            Ok(Values::Range(min, max))
+        } else {
+            Ok(Values::List(
+                s.split(',').map(parse).collect::<Result<_, _>>()?,
+            ))
+        }
+    }
+}
+
+impl<T: Copy> Values<T> {
+    fn list(&self) -> Result<&[T], Box<dyn Error>> {
+        match self {
+            Values::List(values) => Ok(values),
+            Values::Range(..) => Err("Ranges require --search random".into()),
+        }
+    }
+
+    fn sample(&self, prng: &mut Prng, uniform: impl Fn(T, T, f64) -> T) -> T {
+        match self {
+            Values::List(values) => values[(prng.next() * values.len() as f64) as usize],
+            Values::Range(min, max) => uniform(*min, *max, prng.next()),
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct SearchOptions {
+    #[arg(long, default_value = "8")]
+    factors: Values<u32>,
+
+    #[arg(long, default_value = "20")]
+    iterations: Values<u32>,
+
+    #[arg(long)]
+    regularization: Option<Values<f32>>,
+
+    #[arg(long)]
+    learning_rate: Option<Values<f32>>,
+
+    #[arg(long)]
+    alpha: Option<Values<f32>>,
+
+    #[arg(long, default_value_t = Search::Grid, value_parser = PossibleValuesParser::new(Search::variants()).map(|s| s.parse::<Search>().unwrap()))]
+    search: Search,
+
+    #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(u32).range(1..))]
+    trials: u32,
+
+    #[arg(long)]
+    seed: Option<u64>,
+}
+
+fn grid(options: &SearchOptions) -> Result<Vec<TrainOptions>, Box<dyn Error>> {
+    let mut candidates = vec![TrainOptions::new(8, 20)];
+    candidates = expand(candidates, options.factors.list()?, |o, v| o.factors = v);
+    candidates = expand(candidates, options.iterations.list()?, |o, v| {
+        o.iterations = v
+    });
+    if let Some(values) = &options.regularization {
+        candidates = expand(candidates, values.list()?, |o, v| {
+            o.regularization = Some(v)
+        });
+    }
+    if let Some(values) = &options.learning_rate {
+        candidates = expand(candidates, values.list()?, |o, v| o.learning_rate = Some(v));
+    }
+    if let Some(values) = &options.alpha {
+        candidates = expand(candidates, values.list()?, |o, v| o.alpha = Some(v));
+    }
+    for candidate in &mut candidates {
+        candidate.seed = options.seed;
+    }
+    Ok(candidates)
+}
+
+// cartesian product with the values of a parameter
+fn expand<T: Copy>(
+    candidates: Vec<TrainOptions>,
+    values: &[T],
+    set: impl Fn(&mut TrainOptions, T),
+) -> Vec<TrainOptions> {
+    let mut expanded = Vec::with_capacity(candidates.len() * values.len());
+    for candidate in candidates {
+        for v in values {
+            let mut c = candidate.clone();
+            set(&mut c, *v);
+            expanded.push(c);
+        }
+    }
+    expanded
+}
+
+fn random(options: &SearchOptions) -> Vec<TrainOptions> {
+    let mut prng = Prng::new(options.seed);
+    let uniform_u32 = |min: u32, max: u32, r: f64| min + (r * (max - min + 1) as f64) as u32;
+    let uniform_f32 = |min: f32, max: f32, r: f64| min + r as f32 * (max - min);
+
+    (0..options.trials)
+        .map(|_| {
+            let mut train_options = TrainOptions::new(
+                options.factors.sample(&mut prng, uniform_u32),
+                options.iterations.sample(&mut prng, uniform_u32),
+            );
+            train_options.regularization = options
+                .regularization
+                .as_ref()
+                .map(|v| v.sample(&mut prng, uniform_f32));
+            train_options.learning_rate = options
+                .learning_rate
+                .as_ref()
+                .map(|v| v.sample(&mut prng, uniform_f32));
+            train_options.alpha = options
+                .alpha
+                .as_ref()
+                .map(|v| v.sample(&mut prng, uniform_f32));
+            train_options.seed = options.seed;
+            train_options
+        })
+        .collect()
+}
+
+// searched parameters and their values (none for the default)
+fn params(train_options: &TrainOptions) -> [(&'static str, Option<String>); 5] {
+    [
+        ("factors", Some(train_options.factors.to_string())),
+        ("iterations", Some(train_options.iterations.to_string())),
+        (
+            "regularization",
+            train_options.regularization.map(|v| v.to_string()),
+        ),
+        (
+            "learning_rate",
+            train_options.learning_rate.map(|v| v.to_string()),
+        ),
+        ("alpha", train_options.alpha.map(|v| v.to_string())),
+    ]
+}
+
+pub fn tune(
+    input: &Path,
+    output: &Path,
+    input_options: &InputOptions,
+    split_options: &SplitOptions,
+    search_options: &SearchOptions,
+    count: usize,
+    overwrite: bool,
+) -> Result<(), Box<dyn Error>> {
+    if !overwrite {
+        check_exists(output)?;
+    }
+
+    let mut candidates = match search_options.search {
+        Search::Grid => grid(search_options)?,
+        Search::Random => random(search_options),
+    };
+    if candidates.iter().any(|c| c.factors == 0) {
+        return Err("Factors must be at least 1".into());
+    }
+
+    let interactions =
+        read_interactions(input, input_options, split_options.requires_timestamps())?;
+    let (train_set, test_set) = split(&interactions, split_options, search_options.seed);
+    if train_set.is_empty() || test_set.is_empty() {
+        return Err("Not enough data to split".into());
+    }
+
+    if interactions.explicit {
+        eprintln!("Using explicit feedback");
+    } else {
+        eprintln!("Using implicit feedback");
+    }
+
+    let bar = progress_bar(
+        candidates.len() as u64,
+        "Tuning",
+        "{msg} {wide_bar} {pos}/{len}",
+    );
+
+    let mut results = Vec::with_capacity(candidates.len());
+    for train_options in candidates.drain(..) {
+        let model = fit_with_callback(&interactions, &train_set, &train_options, |_| {});
+        let metrics = metrics(&model, &interactions, &test_set, count)?;
+        results.push((train_options, metrics));
+        bar.inc(1);
+    }
+
+    bar.finish();
+
+    // lower RMSE is better for explicit feedback, higher NDCG for implicit
+    let key = |m: &Metrics| {
+        let value = match m {
+            Metrics::Explicit { rmse, .. } => *rmse,
+            Metrics::Implicit { ndcg, .. } => -ndcg,
+        };
+        if value.is_nan() { f64::INFINITY } else { value }
+    };
+    results.sort_by(|a, b| key(&a.1).total_cmp(&key(&b.1)));
+
+    let metric_names: Vec<String> = results[0]
+        .1
+        .names(count)
+        .iter()
+        .map(|n| n.to_lowercase())
+        .collect();
+
+    let json = format_extension(output) == Some("json");
+    if json {
+        let mut wtr = create_output(output, overwrite)?;
+        writeln!(wtr, "[")?;
+        for (i, (train_options, metrics)) in results.iter().enumerate() {
+            let mut fields: Vec<String> = params(train_options)
+                .into_iter()
+                .filter_map(|(name, value)| value.map(|v| format!("\"{name}\": {v}")))
+                .collect();
+            for (name, value) in metric_names.iter().zip(metrics.values()) {
+                fields.push(format!("\"{}\": {}", name, json_number(value)));
+            }
+            let comma = if i + 1 < results.len() { "," } else { "" };
+            writeln!(wtr, "  {{{}}}{}", fields.join(", "), comma)?;
+        }
+        writeln!(wtr, "]")?;
+        wtr.finish()?;
+    } else {
+        let mut wtr = create_csv(output, overwrite)?;
+        let mut header: Vec<String> = params(&results[0].0)
+            .iter()
+            .map(|(name, _)| name.to_string())
+            .collect();
+        header.extend(metric_names.iter().cloned());
+        wtr.write_record(&header)?;
+        for (train_options, metrics) in &results {
+            let mut record: Vec<String> = params(train_options)
+                .into_iter()
+                .map(|(_, value)| value.unwrap_or_default())
+                .collect();
+            record.extend(metrics.values().iter().map(|v| v.to_string()));
+            wtr.write_record(&record)?;
+        }
+        finish_csv(wtr)?;
+    }
+
+    let (best, best_metrics) = &results[0];
+    let options: Vec<String> = params(best)
+        .into_iter()
+        .filter_map(|(name, value)| value.map(|v| format!("--{} {}", name.replace('_', "-"), v)))
+        .collect();
+    // keep stdout for the results when writing them there
+    let print = |line: String| {
+        if is_stdio(output) {
+            eprintln!("{line}");
+        } else {
+            println!("{line}");
+        }
+    };
+    print(format!("Best: {}", options.join(" ")));
+    for (name, value) in best_metrics.names(count).iter().zip(best_metrics.values()) {
+        print(format!("{name}: {value:.4}"));
+    }
+
+    if !is_stdio(output) {
+        eprintln!("Saved to {}", output.display());
+    }
+
+    Ok(())
+}